@@ -0,0 +1,85 @@
+//! Decoding of configuration space addresses.
+//!
+//! The functions in this module translate the addresses used by the platform
+//! configuration access mechanisms into a `(bus, device, function, offset)`
+//! tuple, `offset` being the byte offset consumed by the
+//! [`PciConfig`](../pci_config/trait.PciConfig.html) accessors.
+
+/// Size of the ECAM window of a single bus.
+const ECAM_BUS_SIZE: u64 = 1 << 20;
+/// Number of buses an ECAM window can cover.
+const ECAM_NUM_BUSES: u64 = 256;
+
+/// Maximum device number on a bus.
+pub const MAX_DEVICE: u8 = 31;
+/// Maximum function number of a device.
+pub const MAX_FUNCTION: u8 = 7;
+
+/// Decodes the ECAM address `addr` of an ECAM window starting at `base`.
+///
+/// Returns `(bus, device, function, offset)`, or `None` if `addr` is outside
+/// of the window.
+pub fn decode_ecam(addr: u64, base: u64) -> Option<(u8, u8, u8, usize)> {
+    let addr = addr.checked_sub(base)?;
+    if addr >= ECAM_NUM_BUSES * ECAM_BUS_SIZE {
+        return None;
+    }
+    Some((
+        (addr >> 20) as u8,
+        ((addr >> 15) & 0x1f) as u8,
+        ((addr >> 12) & 0x7) as u8,
+        (addr & 0xfff) as usize,
+    ))
+}
+
+/// Encodes `(bus, device, function, offset)` into an address of the ECAM
+/// window starting at `base`.
+///
+/// Returns `None` if one of the components is out of range or the address
+/// overflows.
+pub fn encode_ecam(base: u64, bus: u8, device: u8, function: u8, offset: usize) -> Option<u64> {
+    if device > MAX_DEVICE || function > MAX_FUNCTION || offset > 0xfff {
+        return None;
+    }
+    base.checked_add(
+        u64::from(bus) << 20 | u64::from(device) << 15 | u64::from(function) << 12 | offset as u64,
+    )
+}
+
+/// Enable bit of the 0xCF8 configuration address register.
+const CF8_ENABLE: u32 = 1 << 31;
+
+/// Decodes the value written to the legacy 0xCF8 configuration address port.
+///
+/// Returns `(bus, device, function, offset)`, `offset` being the dword aligned
+/// register offset; the byte accessed within the register is given by the
+/// 0xCFC-0xCFF data port used. Returns `None` if the enable bit is clear.
+pub fn decode_cf8(value: u32) -> Option<(u8, u8, u8, usize)> {
+    if value & CF8_ENABLE == 0 {
+        return None;
+    }
+    Some((
+        (value >> 16) as u8,
+        ((value >> 11) & 0x1f) as u8,
+        ((value >> 8) & 0x7) as u8,
+        (value & 0xfc) as usize,
+    ))
+}
+
+/// Encodes `(bus, device, function, offset)` into a 0xCF8 configuration
+/// address with the enable bit set.
+///
+/// Returns `None` if one of the components is out of range or `offset` is not
+/// a dword aligned offset within the first 256 bytes.
+pub fn encode_cf8(bus: u8, device: u8, function: u8, offset: usize) -> Option<u32> {
+    if device > MAX_DEVICE || function > MAX_FUNCTION || offset & !0xfc != 0 {
+        return None;
+    }
+    Some(
+        CF8_ENABLE
+            | u32::from(bus) << 16
+            | u32::from(device) << 11
+            | u32::from(function) << 8
+            | offset as u32,
+    )
+}
@@ -0,0 +1,159 @@
+//! Address Translation Services (ATS) extended capability.
+
+use crate::capability::write_u16;
+use crate::extended_capability::{PciExtendedCapability, PciExtendedCapabilityId};
+
+/// Offset of the ATS Capability register.
+pub const CAPABILITY_OFFSET: usize = 0x04;
+/// Offset of the ATS Control register.
+pub const CONTROL_OFFSET: usize = 0x06;
+
+/// ATS Capability bits holding the Invalidate Queue Depth.
+pub const CAPABILITY_INVALIDATE_QUEUE_DEPTH_MASK: u16 = 0x1f;
+/// ATS Capability bit set when translation requests must be page aligned.
+pub const CAPABILITY_PAGE_ALIGNED_REQUEST: u16 = 1 << 5;
+/// ATS Capability bit set when global invalidation is supported.
+pub const CAPABILITY_GLOBAL_INVALIDATE: u16 = 1 << 6;
+/// ATS Control bits holding the Smallest Translation Unit.
+pub const CONTROL_STU_MASK: u16 = 0x1f;
+/// ATS Control bit enabling ATS.
+pub const CONTROL_ENABLE: u16 = 1 << 15;
+
+/// ATS extended capability.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct AtsCap {
+    /// ATS Capability register.
+    pub capability: u16,
+    /// ATS Control register.
+    pub control: u16,
+}
+
+impl AtsCap {
+    /// ID of the extended capability.
+    pub const ID: PciExtendedCapabilityId = PciExtendedCapabilityId::AddressTranslationServices;
+    /// Version of the extended capability.
+    pub const VERSION: u8 = 1;
+    /// Length of the extended capability in bytes.
+    pub const LEN: usize = 8;
+
+    /// Creates a disabled ATS capability.
+    ///
+    /// An `invalidate_queue_depth` of 0 stands for a depth of 32, larger
+    /// values are truncated to 5 bits.
+    pub fn new(
+        invalidate_queue_depth: u8,
+        page_aligned_request: bool,
+        global_invalidate: bool,
+    ) -> Self {
+        let mut capability =
+            u16::from(invalidate_queue_depth) & CAPABILITY_INVALIDATE_QUEUE_DEPTH_MASK;
+        if page_aligned_request {
+            capability |= CAPABILITY_PAGE_ALIGNED_REQUEST;
+        }
+        if global_invalidate {
+            capability |= CAPABILITY_GLOBAL_INVALIDATE;
+        }
+        AtsCap {
+            capability,
+            control: 0,
+        }
+    }
+
+    /// Returns the Invalidate Queue Depth field.
+    pub fn invalidate_queue_depth(&self) -> u8 {
+        (self.capability & CAPABILITY_INVALIDATE_QUEUE_DEPTH_MASK) as u8
+    }
+
+    /// Returns whether translation requests must be page aligned.
+    pub fn page_aligned_request(&self) -> bool {
+        self.capability & CAPABILITY_PAGE_ALIGNED_REQUEST != 0
+    }
+
+    /// Returns whether global invalidation is supported.
+    pub fn global_invalidate(&self) -> bool {
+        self.capability & CAPABILITY_GLOBAL_INVALIDATE != 0
+    }
+
+    /// Returns whether ATS is enabled.
+    pub fn enabled(&self) -> bool {
+        self.control & CONTROL_ENABLE != 0
+    }
+
+    /// Enables or disables ATS.
+    pub fn set_enabled(&mut self, enabled: bool) {
+        if enabled {
+            self.control |= CONTROL_ENABLE;
+        } else {
+            self.control &= !CONTROL_ENABLE;
+        }
+    }
+
+    /// Returns the Smallest Translation Unit, the log2 of its size in 4 KiB
+    /// pages.
+    pub fn stu(&self) -> u8 {
+        (self.control & CONTROL_STU_MASK) as u8
+    }
+
+    /// Sets the Smallest Translation Unit, truncated to 5 bits.
+    pub fn set_stu(&mut self, stu: u8) {
+        self.control = (self.control & !CONTROL_STU_MASK) | (u16::from(stu) & CONTROL_STU_MASK);
+    }
+}
+
+impl PciExtendedCapability for AtsCap {
+    fn id(&self) -> PciExtendedCapabilityId {
+        Self::ID
+    }
+
+    fn version(&self) -> u8 {
+        Self::VERSION
+    }
+
+    fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = vec![0; Self::LEN];
+        write_u16(&mut bytes, CAPABILITY_OFFSET, self.capability);
+        write_u16(&mut bytes, CONTROL_OFFSET, self.control);
+        bytes
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config_space::ConfigSpace;
+    use crate::extended_capability::ExtendedCapabilityRegion;
+    use crate::pci_config::{Error, PciConfig};
+
+    #[test]
+    fn test_install() {
+        let mut config = ConfigSpace::new_pcie();
+        let mut ats = AtsCap::new(4, true, false);
+        ats.set_stu(2);
+        assert_eq!(
+            config.install_extended_capabilities(&[&ats]).unwrap(),
+            vec![0x100]
+        );
+
+        assert_eq!(
+            config.extended_capabilities().unwrap(),
+            vec![ExtendedCapabilityRegion {
+                id: AtsCap::ID,
+                version: AtsCap::VERSION,
+                offset: 0x100,
+            }]
+        );
+        assert_eq!(config.read_register(0x100 / 4).unwrap(), 0x0001_000F);
+        assert_eq!(config.read_word(0x100 + CAPABILITY_OFFSET).unwrap(), 0x0024);
+        assert_eq!(config.read_word(0x100 + CONTROL_OFFSET).unwrap(), 0x0002);
+    }
+
+    #[test]
+    fn test_install_conventional() {
+        let mut config = ConfigSpace::new();
+        let ats = AtsCap::default();
+        assert_eq!(
+            config.install_extended_capabilities(&[&ats]),
+            Err(Error::CapabilitiesOverflow(8))
+        );
+    }
+}
@@ -0,0 +1,801 @@
+//! Base Address Register (BAR) regions.
+
+use std::convert::TryFrom;
+use std::fmt;
+
+use crate::pci_config::{self, PciConfig};
+use crate::{bridge, device};
+
+/// Minimum size of a memory BAR.
+const MIN_MEMORY_BAR_SIZE: u64 = 16;
+/// Minimum size of an I/O BAR.
+const MIN_IO_BAR_SIZE: u64 = 4;
+
+/// BAR bit set for I/O space BARs.
+pub const BAR_IO_SPACE: u32 = 0x1;
+/// Memory BAR bits holding the memory type.
+pub const BAR_MEM_TYPE_MASK: u32 = 0x6;
+/// Memory type of 64-bit memory BARs.
+pub const BAR_MEM_TYPE_64: u32 = 0x4;
+/// Memory type of the legacy BARs that must be placed below 1 MiB.
+pub const BAR_MEM_TYPE_BELOW_1M: u32 = 0x2;
+/// Memory BAR bit set for prefetchable memory.
+pub const BAR_PREFETCHABLE: u32 = 0x8;
+/// I/O BAR bits holding the base address.
+pub const BAR_IO_ADDRESS_MASK: u32 = !0x3;
+/// Memory BAR bits holding the base address.
+pub const BAR_MEM_ADDRESS_MASK: u32 = !0xf;
+
+/// Expansion ROM BAR bit enabling the decoding of the ROM.
+pub const ROM_BAR_ENABLE: u32 = 1;
+/// Expansion ROM BAR bits holding the base address.
+pub const ROM_BAR_ADDRESS_MASK: u32 = 0xFFFF_F800;
+
+/// Errors related to BARs.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Error {
+    /// The region starting at the first value with the length given by the
+    /// second value does not fit in the BAR address space.
+    BarAddressInvalid(u64, u64),
+    /// The BAR region is empty. Such a BAR can't be sized by the guest.
+    BarSizeZero,
+    /// An I/O region can't be 64-bit.
+    BarIo64,
+    /// The BAR index is out of range.
+    BarIndex(usize),
+    /// A 64-bit BAR can't start at this index, as it has no following BAR.
+    BarInvalid64(usize),
+    /// The BAR at this index would take the upper register of a 64-bit BAR.
+    BarInUse64(usize),
+    /// The BAR at this index is a 64-bit BAR but the upper register is
+    /// missing.
+    BarHighMissing(usize),
+    /// The BAR at this index has the reserved memory type.
+    BarMemoryTypeReserved(usize),
+    /// The BAR at this index has the legacy below 1 MiB memory type, which
+    /// `PciBarRegion` can't represent.
+    BarMemoryTypeBelow1M(usize),
+    /// The BAR at this index is fixed and can't be relocated.
+    BarFixed(usize),
+    /// The device has no expansion ROM of the saved size, this value.
+    RomSizeMismatch(u32),
+    /// Accessing the BAR registers failed.
+    ConfigAccess(pci_config::Error),
+}
+
+impl From<pci_config::Error> for Error {
+    fn from(e: pci_config::Error) -> Self {
+        Error::ConfigAccess(e)
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::BarAddressInvalid(addr, len) => {
+                write!(
+                    f,
+                    "invalid BAR region: address {:#x}, length {:#x}",
+                    addr, len
+                )
+            }
+            Error::BarSizeZero => write!(f, "BAR region is empty"),
+            Error::BarIo64 => write!(f, "I/O BAR region can't be 64-bit"),
+            Error::BarIndex(idx) => write!(f, "invalid BAR index {}", idx),
+            Error::BarInvalid64(idx) => write!(f, "64-bit BAR can't start at index {}", idx),
+            Error::BarInUse64(idx) => {
+                write!(f, "BAR {} is the upper register of a 64-bit BAR", idx)
+            }
+            Error::BarHighMissing(idx) => {
+                write!(f, "missing upper register of 64-bit BAR {}", idx)
+            }
+            Error::BarMemoryTypeReserved(idx) => {
+                write!(f, "BAR {} has the reserved memory type", idx)
+            }
+            Error::BarMemoryTypeBelow1M(idx) => {
+                write!(f, "BAR {} has the legacy below 1 MiB memory type", idx)
+            }
+            Error::BarFixed(idx) => write!(f, "BAR {} is fixed", idx),
+            Error::RomSizeMismatch(size) => {
+                write!(f, "device has no expansion ROM of {:#x} bytes", size)
+            }
+            Error::ConfigAccess(e) => write!(f, "failed to access BAR registers: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+/// Result type for BAR operations.
+pub type Result<T> = std::result::Result<T, Error>;
+
+/// Index of a BAR of a type 0 header.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct BarIndex(usize);
+
+impl BarIndex {
+    /// Creates the index of BAR `n`, failing with `BarIndex` unless `n` is
+    /// below `device::NUM_BARS`.
+    pub fn new(n: usize) -> Result<Self> {
+        if n >= device::NUM_BARS {
+            return Err(Error::BarIndex(n));
+        }
+        Ok(BarIndex(n))
+    }
+
+    /// Returns the index as a number.
+    pub fn get(self) -> usize {
+        self.0
+    }
+}
+
+impl From<BridgeBarIndex> for BarIndex {
+    fn from(idx: BridgeBarIndex) -> Self {
+        BarIndex(idx.0)
+    }
+}
+
+/// Index of a BAR of a type 1 header.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct BridgeBarIndex(usize);
+
+impl BridgeBarIndex {
+    /// Creates the index of BAR `n`, failing with `BarIndex` unless `n` is
+    /// below `bridge::NUM_BARS`.
+    pub fn new(n: usize) -> Result<Self> {
+        if n >= bridge::NUM_BARS {
+            return Err(Error::BarIndex(n));
+        }
+        Ok(BridgeBarIndex(n))
+    }
+
+    /// Returns the index as a number.
+    pub fn get(self) -> usize {
+        self.0
+    }
+}
+
+/// Whether a memory BAR is prefetchable.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PciBarPrefetchable {
+    /// The region is prefetchable.
+    Prefetchable,
+    /// The region is not prefetchable.
+    NotPrefetchable,
+}
+
+/// Memory type of a memory BAR.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BarMemoryType {
+    /// The region can be placed anywhere in the 32-bit memory space.
+    Bits32,
+    /// The region must be placed below 1 MiB. This type was removed from the
+    /// PCI specification but may still be found in legacy devices.
+    Below1M,
+    /// The region can be placed anywhere in the 64-bit memory space.
+    Bits64,
+    /// The reserved memory type.
+    Reserved,
+}
+
+/// Low-order encoding bits of a BAR register.
+///
+/// These are bit 0 for the space indicator and, for memory BARs, bits 1-2 for
+/// the memory type and bit 3 for the prefetchable bit.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BarEncoding {
+    /// I/O space BAR.
+    Io,
+    /// Memory space BAR.
+    Memory {
+        /// Memory type of the BAR.
+        mem_type: BarMemoryType,
+        /// Whether the region is prefetchable.
+        prefetchable: PciBarPrefetchable,
+    },
+}
+
+impl BarEncoding {
+    /// Decodes the low-order bits of a BAR register. Higher bits are ignored.
+    pub fn from_bits(bits: u8) -> Self {
+        let bits = u32::from(bits);
+        if bits & BAR_IO_SPACE != 0 {
+            return BarEncoding::Io;
+        }
+        let mem_type = match bits & BAR_MEM_TYPE_MASK {
+            0 => BarMemoryType::Bits32,
+            BAR_MEM_TYPE_BELOW_1M => BarMemoryType::Below1M,
+            BAR_MEM_TYPE_64 => BarMemoryType::Bits64,
+            _ => BarMemoryType::Reserved,
+        };
+        let prefetchable = if bits & BAR_PREFETCHABLE != 0 {
+            PciBarPrefetchable::Prefetchable
+        } else {
+            PciBarPrefetchable::NotPrefetchable
+        };
+        BarEncoding::Memory {
+            mem_type,
+            prefetchable,
+        }
+    }
+
+    /// Returns the low-order bits of a BAR register with this encoding.
+    pub fn to_bits(self) -> u8 {
+        let bits = match self {
+            BarEncoding::Io => BAR_IO_SPACE,
+            BarEncoding::Memory {
+                mem_type,
+                prefetchable,
+            } => {
+                let mem_type = match mem_type {
+                    BarMemoryType::Bits32 => 0,
+                    BarMemoryType::Below1M => BAR_MEM_TYPE_BELOW_1M,
+                    BarMemoryType::Bits64 => BAR_MEM_TYPE_64,
+                    BarMemoryType::Reserved => BAR_MEM_TYPE_MASK,
+                };
+                match prefetchable {
+                    PciBarPrefetchable::Prefetchable => mem_type | BAR_PREFETCHABLE,
+                    PciBarPrefetchable::NotPrefetchable => mem_type,
+                }
+            }
+        };
+        bits as u8
+    }
+}
+
+/// Configuration of a BAR.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct PciBarConfig {
+    /// Index of the BAR, or of the first of the pair for 64-bit BARs.
+    pub index: BarIndex,
+    /// Region decoded by the BAR.
+    pub region: PciBarRegion,
+    /// Whether the region is prefetchable. Ignored for I/O BARs.
+    pub prefetchable: PciBarPrefetchable,
+    /// Whether the region is fixed. The guest can't relocate a fixed BAR:
+    /// writes to its registers are ignored.
+    pub fixed: bool,
+}
+
+impl PciBarConfig {
+    /// Returns the low-order encoding bits of the BAR register.
+    pub fn encoding(&self) -> BarEncoding {
+        let mem_type = match self.region {
+            PciBarRegion::Io { .. } => return BarEncoding::Io,
+            PciBarRegion::Memory32 { .. } => BarMemoryType::Bits32,
+            PciBarRegion::Memory64 { .. } => BarMemoryType::Bits64,
+        };
+        BarEncoding::Memory {
+            mem_type,
+            prefetchable: self.prefetchable,
+        }
+    }
+
+    /// Returns the bits of the BAR register the guest can write.
+    ///
+    /// These are the address bits above the size of the region, none for a
+    /// fixed BAR. The type bits are always read-only. For a 64-bit BAR, this
+    /// is the mask of the lower register.
+    pub fn write_mask(&self) -> u32 {
+        let size_mask = self.size_mask() as u32;
+        match self.region {
+            PciBarRegion::Io { .. } => size_mask & BAR_IO_ADDRESS_MASK,
+            PciBarRegion::Memory32 { .. } | PciBarRegion::Memory64 { .. } => {
+                size_mask & BAR_MEM_ADDRESS_MASK
+            }
+        }
+    }
+
+    /// Returns the bits of the upper register of a 64-bit BAR the guest can
+    /// write, or `None` for other BARs.
+    pub fn write_mask_high(&self) -> Option<u32> {
+        match self.region {
+            PciBarRegion::Memory64 { .. } => Some((self.size_mask() >> 32) as u32),
+            _ => None,
+        }
+    }
+
+    // Returns the address bits above the size of the region.
+    fn size_mask(&self) -> u64 {
+        if self.fixed {
+            return 0;
+        }
+        let is_io = matches!(self.region, PciBarRegion::Io { .. });
+        !PciBarRegion::required_size(self.region.size(), is_io).wrapping_sub(1)
+    }
+}
+
+/// BAR topology of a device: which BARs are set up, and how.
+///
+/// This is the part of the BAR state that the guest can't change, saved
+/// with [`PciDeviceConfig::save_bars`](../device/trait.PciDeviceConfig.html#method.save_bars).
+/// The base addresses the guest programmed are part of the registers.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct BarState {
+    /// Configuration of the BARs, by increasing index.
+    pub bars: Vec<PciBarConfig>,
+    /// Size of the expansion ROM in bytes, or 0 if the device has none.
+    pub rom_size: u32,
+}
+
+/// Address space region decoded by a BAR.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PciBarRegion {
+    /// Region in the I/O space.
+    Io {
+        /// Base address of the region.
+        addr: u32,
+        /// Length of the region in bytes.
+        len: u32,
+    },
+    /// Region in the 32-bit memory space.
+    Memory32 {
+        /// Base address of the region.
+        addr: u32,
+        /// Length of the region in bytes.
+        len: u32,
+    },
+    /// Region in the 64-bit memory space, decoded by a pair of BARs.
+    Memory64 {
+        /// Base address of the region.
+        addr: u64,
+        /// Length of the region in bytes.
+        len: u64,
+    },
+}
+
+impl PciBarRegion {
+    /// Creates an I/O region of `len` bytes at `addr`.
+    ///
+    /// Like the other region constructors, this fails with `BarSizeZero` if
+    /// `len` is 0. The region may end at the top of its address space, but
+    /// not wrap around it.
+    pub fn new_io_region(addr: u32, len: u32) -> Result<Self> {
+        check_len(u64::from(len))?;
+        addr.checked_add(len - 1)
+            .ok_or_else(|| Error::BarAddressInvalid(u64::from(addr), u64::from(len)))?;
+        Ok(PciBarRegion::Io { addr, len })
+    }
+
+    /// Creates a 32-bit memory region of `len` bytes at `addr`.
+    pub fn new_32bit_mem_region(addr: u32, len: u32) -> Result<Self> {
+        check_len(u64::from(len))?;
+        addr.checked_add(len - 1)
+            .ok_or_else(|| Error::BarAddressInvalid(u64::from(addr), u64::from(len)))?;
+        Ok(PciBarRegion::Memory32 { addr, len })
+    }
+
+    /// Creates a 64-bit memory region of `len` bytes at `addr`.
+    pub fn new_64bit_mem_region(addr: u64, len: u64) -> Result<Self> {
+        check_len(len)?;
+        addr.checked_add(len - 1)
+            .ok_or(Error::BarAddressInvalid(addr, len))?;
+        Ok(PciBarRegion::Memory64 { addr, len })
+    }
+
+    /// Creates a 64-bit memory region of `len` bytes at `addr` that must be
+    /// addressable with `max_bits` address bits.
+    ///
+    /// This rejects regions placed beyond the guest physical address width of
+    /// the platform.
+    pub fn new_64bit_mem_region_bounded(addr: u64, len: u64, max_bits: u32) -> Result<Self> {
+        check_len(len)?;
+        let last = addr
+            .checked_add(len - 1)
+            .ok_or(Error::BarAddressInvalid(addr, len))?;
+        if max_bits < 64 && last >> max_bits != 0 {
+            return Err(Error::BarAddressInvalid(addr, len));
+        }
+        Ok(PciBarRegion::Memory64 { addr, len })
+    }
+
+    /// Creates a region from its `(base, len, is_io, is_64bit)` description,
+    /// the one [`as_tuple`](#method.as_tuple) returns.
+    ///
+    /// Fails like the other region constructors, with `BarAddressInvalid` if
+    /// an I/O or 32-bit memory region doesn't fit in 32 bits, and with
+    /// `BarIo64` if both `is_io` and `is_64bit` are set.
+    pub fn from_tuple((base, len, is_io, is_64bit): (u64, u64, bool, bool)) -> Result<Self> {
+        if is_64bit {
+            if is_io {
+                return Err(Error::BarIo64);
+            }
+            return Self::new_64bit_mem_region(base, len);
+        }
+        let (addr, len) = match (u32::try_from(base), u32::try_from(len)) {
+            (Ok(addr), Ok(len)) => (addr, len),
+            _ => return Err(Error::BarAddressInvalid(base, len)),
+        };
+        if is_io {
+            Self::new_io_region(addr, len)
+        } else {
+            Self::new_32bit_mem_region(addr, len)
+        }
+    }
+
+    /// Returns the region as `(base, len, is_io, is_64bit)`, for code that
+    /// prefers flat data to matching on the variants.
+    pub fn as_tuple(&self) -> (u64, u64, bool, bool) {
+        (
+            self.addr(),
+            self.size(),
+            matches!(self, PciBarRegion::Io { .. }),
+            matches!(self, PciBarRegion::Memory64 { .. }),
+        )
+    }
+
+    // Returns the base address of the region, in its address space.
+    pub(crate) fn addr(&self) -> u64 {
+        match *self {
+            PciBarRegion::Io { addr, .. } | PciBarRegion::Memory32 { addr, .. } => u64::from(addr),
+            PciBarRegion::Memory64 { addr, .. } => addr,
+        }
+    }
+
+    // Returns the length of the region in bytes.
+    pub(crate) fn size(&self) -> u64 {
+        match *self {
+            PciBarRegion::Io { len, .. } | PciBarRegion::Memory32 { len, .. } => u64::from(len),
+            PciBarRegion::Memory64 { len, .. } => len,
+        }
+    }
+
+    /// Returns whether `addr` falls in the region.
+    ///
+    /// `addr` is in the address space of the region, I/O or memory.
+    pub fn contains(&self, addr: u64) -> bool {
+        addr >= self.addr() && addr - self.addr() < self.size()
+    }
+
+    /// Returns whether the region overlaps with `other`.
+    ///
+    /// I/O regions only overlap with I/O regions, and memory regions with
+    /// memory regions, whether 32-bit or 64-bit.
+    pub fn intersects(&self, other: &PciBarRegion) -> bool {
+        let is_io = |region: &PciBarRegion| matches!(region, PciBarRegion::Io { .. });
+        if is_io(self) != is_io(other) {
+            return false;
+        }
+        if self.addr() <= other.addr() {
+            other.addr() - self.addr() < self.size() && other.size() != 0
+        } else {
+            other.contains(self.addr()) && self.size() != 0
+        }
+    }
+
+    /// Returns the size a BAR needs to decode `requested_len` bytes.
+    ///
+    /// BAR sizes are powers of two of at least 16 bytes for memory and 4 bytes
+    /// for I/O. Returns 0 if the size does not fit in 64 bits.
+    pub fn required_size(requested_len: u64, is_io: bool) -> u64 {
+        let min = if is_io {
+            MIN_IO_BAR_SIZE
+        } else {
+            MIN_MEMORY_BAR_SIZE
+        };
+        requested_len
+            .max(min)
+            .checked_next_power_of_two()
+            .unwrap_or(0)
+    }
+}
+
+// Checks that a region of `len` bytes isn't empty.
+fn check_len(len: u64) -> Result<()> {
+    if len == 0 {
+        return Err(Error::BarSizeZero);
+    }
+    Ok(())
+}
+
+/// Returns the value an expansion ROM BAR decoding `size` bytes reads back
+/// after the guest wrote all ones to its address bits.
+///
+/// The enable bit isn't part of the probe and reads back as `enabled`. A `size`
+/// of 0 stands for an unimplemented ROM BAR, which reads back as 0.
+pub fn rom_probe_mask(size: u32, enabled: bool) -> u32 {
+    if size == 0 {
+        return 0;
+    }
+    let size_mask = size
+        .checked_next_power_of_two()
+        .map_or(u32::MAX, |size| size - 1);
+    (!size_mask & ROM_BAR_ADDRESS_MASK) | if enabled { ROM_BAR_ENABLE } else { 0 }
+}
+
+// The ROM BAR lives at a different offset in type 0 and type 1 headers.
+pub(crate) fn read_rom_base_address<C: PciConfig + ?Sized>(
+    cfg: &C,
+    rom_bar_offset: usize,
+) -> pci_config::Result<u32> {
+    Ok(cfg.read_register(rom_bar_offset / 4)? & ROM_BAR_ADDRESS_MASK)
+}
+
+pub(crate) fn write_rom_base_address<C: PciConfig + ?Sized>(
+    cfg: &mut C,
+    rom_bar_offset: usize,
+    addr: u32,
+) -> pci_config::Result<()> {
+    let enable = cfg.read_register(rom_bar_offset / 4)? & ROM_BAR_ENABLE;
+    cfg.write_register(rom_bar_offset / 4, (addr & ROM_BAR_ADDRESS_MASK) | enable)
+}
+
+pub(crate) fn read_rom_enabled<C: PciConfig + ?Sized>(
+    cfg: &C,
+    rom_bar_offset: usize,
+) -> pci_config::Result<bool> {
+    Ok(cfg.read_register(rom_bar_offset / 4)? & ROM_BAR_ENABLE != 0)
+}
+
+pub(crate) fn write_rom_enable<C: PciConfig + ?Sized>(
+    cfg: &mut C,
+    rom_bar_offset: usize,
+    enable: bool,
+) -> pci_config::Result<()> {
+    let reg = cfg.read_register(rom_bar_offset / 4)? & !ROM_BAR_ENABLE;
+    let enable = if enable { ROM_BAR_ENABLE } else { 0 };
+    cfg.write_register(rom_bar_offset / 4, reg | enable)
+}
+
+// Reads the base address of the BAR at `idx` of the BARs starting at
+// `bars_offset`, out of `num_bars`.
+pub(crate) fn read_bar_address<C: PciConfig + ?Sized>(
+    cfg: &C,
+    bars_offset: usize,
+    num_bars: usize,
+    idx: usize,
+) -> Result<u64> {
+    let reg_idx = bars_offset / 4 + idx;
+    let low = cfg.read_register(reg_idx)?;
+    let addr = u64::from(low & BAR_MEM_ADDRESS_MASK);
+    match BarEncoding::from_bits(low as u8) {
+        BarEncoding::Io => return Ok(u64::from(low & BAR_IO_ADDRESS_MASK)),
+        BarEncoding::Memory {
+            mem_type: BarMemoryType::Bits64,
+            ..
+        } => (),
+        BarEncoding::Memory { .. } => return Ok(addr),
+    }
+    if idx + 1 >= num_bars {
+        return Err(Error::BarInvalid64(idx));
+    }
+    let high = cfg.read_register(reg_idx + 1)?;
+    Ok(u64::from(high) << 32 | addr)
+}
+
+// Returns the value of the BAR register(s) for `config`, the upper half being
+// set for 64-bit BARs only.
+pub(crate) fn encode_registers(config: &PciBarConfig) -> (u32, Option<u32>) {
+    let bits = u32::from(config.encoding().to_bits());
+    match config.region {
+        PciBarRegion::Io { addr, .. } => ((addr & BAR_IO_ADDRESS_MASK) | bits, None),
+        PciBarRegion::Memory32 { addr, .. } => ((addr & BAR_MEM_ADDRESS_MASK) | bits, None),
+        PciBarRegion::Memory64 { addr, .. } => (
+            (addr as u32 & BAR_MEM_ADDRESS_MASK) | bits,
+            Some((addr >> 32) as u32),
+        ),
+    }
+}
+
+/// Returns the index of the first register of the BAR described by `config`,
+/// in the configuration space, and the values to write there.
+///
+/// These are the registers implementations of
+/// [`PciDeviceConfig::add_bar`](../device/trait.PciDeviceConfig.html#tymethod.add_bar)
+/// set up: one register holding the encoding bits and the base address, and
+/// a second one holding the upper half of the address for 64-bit BARs.
+pub fn encode_bar(config: &PciBarConfig) -> (usize, Vec<u32>) {
+    let reg_idx = device::BARS_START_OFFSET / 4 + config.index.get();
+    let (low, high) = encode_registers(config);
+    (reg_idx, std::iter::once(low).chain(high).collect())
+}
+
+/// Returns the configuration of the BAR at `index` from the contents of its
+/// registers.
+///
+/// `low` is the value of the BAR register and `high` the value of the next
+/// one, only used for 64-bit BARs. The prefetchable bit is taken from `low`,
+/// for 64-bit BARs too. The length of the region can't be read from the
+/// registers, it takes probing the BAR, so it is passed as `len`. The BAR is
+/// reported as relocatable.
+///
+/// BARs with the legacy below 1 MiB memory type fail with
+/// `BarMemoryTypeBelow1M` rather than being taken for 32-bit BARs: the region
+/// would lose its placement constraint.
+pub fn decode_bar(index: BarIndex, low: u32, high: Option<u32>, len: u64) -> Result<PciBarConfig> {
+    let idx = index.get();
+    let len32 =
+        |addr: u32| u32::try_from(len).map_err(|_| Error::BarAddressInvalid(u64::from(addr), len));
+    let (region, prefetchable) = match BarEncoding::from_bits(low as u8) {
+        BarEncoding::Io => {
+            let addr = low & BAR_IO_ADDRESS_MASK;
+            (
+                PciBarRegion::new_io_region(addr, len32(addr)?)?,
+                PciBarPrefetchable::NotPrefetchable,
+            )
+        }
+        BarEncoding::Memory {
+            mem_type,
+            prefetchable,
+        } => {
+            let addr = low & BAR_MEM_ADDRESS_MASK;
+            let region = match mem_type {
+                BarMemoryType::Bits32 => PciBarRegion::new_32bit_mem_region(addr, len32(addr)?)?,
+                BarMemoryType::Bits64 => {
+                    if idx + 1 >= device::NUM_BARS {
+                        return Err(Error::BarInvalid64(idx));
+                    }
+                    let high = high.ok_or(Error::BarHighMissing(idx))?;
+                    PciBarRegion::new_64bit_mem_region(
+                        u64::from(high) << 32 | u64::from(addr),
+                        len,
+                    )?
+                }
+                BarMemoryType::Below1M => return Err(Error::BarMemoryTypeBelow1M(idx)),
+                BarMemoryType::Reserved => return Err(Error::BarMemoryTypeReserved(idx)),
+            };
+            (region, prefetchable)
+        }
+    };
+    Ok(PciBarConfig {
+        index,
+        region,
+        prefetchable,
+        fixed: false,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_required_size() {
+        assert_eq!(PciBarRegion::required_size(5000, false), 8192);
+        assert_eq!(PciBarRegion::required_size(1, false), 16);
+        assert_eq!(PciBarRegion::required_size(1, true), 4);
+        assert_eq!(PciBarRegion::required_size(4096, false), 4096);
+        assert_eq!(PciBarRegion::required_size(u64::MAX, false), 0);
+    }
+
+    #[test]
+    fn test_rom_probe_mask() {
+        assert_eq!(rom_probe_mask(0x1_0000, true), 0xFFFF_0001);
+        assert_eq!(rom_probe_mask(0x1_0000, false), 0xFFFF_0000);
+        assert_eq!(rom_probe_mask(0, true), 0);
+    }
+
+    #[test]
+    fn test_bar_encoding() {
+        for bits in 0..0x10u8 {
+            let encoding = BarEncoding::from_bits(bits);
+            if bits & 0x1 != 0 {
+                assert_eq!(encoding, BarEncoding::Io);
+                assert_eq!(encoding.to_bits(), 0x1);
+            } else {
+                assert_eq!(encoding.to_bits(), bits);
+            }
+        }
+        assert_eq!(
+            BarEncoding::from_bits(0xC),
+            BarEncoding::Memory {
+                mem_type: BarMemoryType::Bits64,
+                prefetchable: PciBarPrefetchable::Prefetchable,
+            }
+        );
+        assert_eq!(
+            BarEncoding::from_bits(0x2),
+            BarEncoding::Memory {
+                mem_type: BarMemoryType::Below1M,
+                prefetchable: PciBarPrefetchable::NotPrefetchable,
+            }
+        );
+        assert_eq!(
+            BarEncoding::from_bits(0x6),
+            BarEncoding::Memory {
+                mem_type: BarMemoryType::Reserved,
+                prefetchable: PciBarPrefetchable::NotPrefetchable,
+            }
+        );
+        assert_eq!(BarEncoding::from_bits(0xF1), BarEncoding::Io);
+    }
+
+    #[test]
+    fn test_zero_length_region() {
+        assert_eq!(
+            PciBarRegion::new_io_region(0x1000, 0),
+            Err(Error::BarSizeZero)
+        );
+        assert_eq!(
+            PciBarRegion::new_32bit_mem_region(0x1000, 0),
+            Err(Error::BarSizeZero)
+        );
+        assert_eq!(
+            PciBarRegion::new_64bit_mem_region(0x1000, 0),
+            Err(Error::BarSizeZero)
+        );
+        assert!(PciBarRegion::new_io_region(0x1000, 1).is_ok());
+    }
+
+    #[test]
+    fn test_encode_bar_64bit_prefetchable() {
+        let config = PciBarConfig {
+            index: BarIndex::new(2).unwrap(),
+            region: PciBarRegion::new_64bit_mem_region(0xC000_0000_0000_0000, 0x1_0000).unwrap(),
+            prefetchable: PciBarPrefetchable::Prefetchable,
+            fixed: false,
+        };
+        assert_eq!(encode_bar(&config), (6, vec![0x0000_000C, 0xC000_0000]));
+    }
+
+    #[test]
+    fn test_region_boundaries() {
+        let region = PciBarRegion::new_32bit_mem_region(0x1000, 0x1000).unwrap();
+        assert!(!region.contains(0xFFF));
+        assert!(region.contains(0x1000));
+        assert!(region.contains(0x1FFF));
+        assert!(!region.contains(0x2000));
+
+        let top = PciBarRegion::new_64bit_mem_region(u64::MAX - 0xFFF, 0x1000).unwrap();
+        assert!(top.contains(u64::MAX));
+        assert!(!top.contains(u64::MAX - 0x1000));
+
+        let before = PciBarRegion::new_64bit_mem_region(0x0, 0x1000).unwrap();
+        let after = PciBarRegion::new_64bit_mem_region(0x2000, 0x10).unwrap();
+        let last_byte = PciBarRegion::new_32bit_mem_region(0x1FFF, 1).unwrap();
+        assert!(!region.intersects(&before));
+        assert!(!before.intersects(&region));
+        assert!(!region.intersects(&after));
+        assert!(!after.intersects(&region));
+        assert!(region.intersects(&last_byte));
+        assert!(last_byte.intersects(&region));
+
+        let io = PciBarRegion::new_io_region(0x1000, 0x10).unwrap();
+        assert!(!region.intersects(&io));
+    }
+
+    #[test]
+    fn test_64bit_region_at_4g() {
+        let bar = |addr| PciBarConfig {
+            index: BarIndex::new(0).unwrap(),
+            region: PciBarRegion::new_64bit_mem_region(addr, 0x2_0000).unwrap(),
+            prefetchable: PciBarPrefetchable::NotPrefetchable,
+            fixed: false,
+        };
+
+        // Below 4 GiB, the region ending at 4 GiB.
+        let below = bar(0xFFFE_0000);
+        assert!(below.region.contains(0xFFFF_FFFF));
+        assert!(!below.region.contains(0x1_0000_0000));
+        assert_eq!(encode_bar(&below).1, vec![0xFFFE_0004, 0x0000_0000]);
+        assert_eq!(
+            decode_bar(BarIndex::new(0).unwrap(), 0xFFFE_0004, Some(0), 0x2_0000).unwrap(),
+            below
+        );
+
+        // Above 4 GiB, the address truncated to 32 bits being 0.
+        let above = bar(0x1_0000_0000);
+        assert_eq!(encode_bar(&above).1, vec![0x0000_0004, 0x0000_0001]);
+        assert_eq!(
+            decode_bar(BarIndex::new(0).unwrap(), 0x0000_0004, Some(1), 0x2_0000).unwrap(),
+            above
+        );
+    }
+
+    #[test]
+    fn test_decode_64bit_prefetchable() {
+        let index = BarIndex::new(0).unwrap();
+        let config = decode_bar(index, 0x8000_000C, Some(0x1), 0x1000).unwrap();
+        assert_eq!(config.prefetchable, PciBarPrefetchable::Prefetchable);
+        assert_eq!(
+            config.region,
+            PciBarRegion::Memory64 {
+                addr: 0x1_8000_0000,
+                len: 0x1000
+            }
+        );
+
+        let config = decode_bar(index, 0x8000_0004, Some(0x1), 0x1000).unwrap();
+        assert_eq!(config.prefetchable, PciBarPrefetchable::NotPrefetchable);
+    }
+}
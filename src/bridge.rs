@@ -0,0 +1,153 @@
+//! Type 1 (PCI-to-PCI bridge) configuration header.
+
+use std::ops::RangeInclusive;
+
+use crate::bar::{self, BridgeBarIndex, PciBarConfig};
+use crate::capability::CAPABILITIES_START_OFFSET;
+use crate::pci_config::{Error, PciConfig, Result, BIST_OFFSET};
+
+/// Offset of the first Base Address Register.
+pub const BARS_START_OFFSET: usize = 0x10;
+/// Number of Base Address Registers in a type 1 header.
+pub const NUM_BARS: usize = 2;
+/// Offset of the Primary Bus Number.
+pub const PRIMARY_BUS_OFFSET: usize = 0x18;
+/// Offset of the Secondary Bus Number.
+pub const SECONDARY_BUS_OFFSET: usize = 0x19;
+/// Offset of the Subordinate Bus Number.
+pub const SUBORDINATE_BUS_OFFSET: usize = 0x1A;
+/// Offset of the Secondary Latency Timer.
+pub const SECONDARY_LATENCY_TIMER_OFFSET: usize = 0x1B;
+/// Offset of the I/O Base register.
+pub const IO_BASE_OFFSET: usize = 0x1C;
+/// Offset of the I/O Limit register.
+pub const IO_LIMIT_OFFSET: usize = 0x1D;
+/// Offset of the Secondary Status register.
+pub const SECONDARY_STATUS_OFFSET: usize = 0x1E;
+/// Offset of the Memory Base register.
+pub const MEMORY_BASE_OFFSET: usize = 0x20;
+/// Offset of the Memory Limit register.
+pub const MEMORY_LIMIT_OFFSET: usize = 0x22;
+/// Offset of the Prefetchable Memory Base register.
+pub const PREFETCHABLE_MEMORY_BASE_OFFSET: usize = 0x24;
+/// Offset of the Prefetchable Memory Limit register.
+pub const PREFETCHABLE_MEMORY_LIMIT_OFFSET: usize = 0x26;
+/// Offset of the Prefetchable Base Upper 32 Bits register.
+pub const PREFETCHABLE_BASE_UPPER_OFFSET: usize = 0x28;
+/// Offset of the Prefetchable Limit Upper 32 Bits register.
+pub const PREFETCHABLE_LIMIT_UPPER_OFFSET: usize = 0x2C;
+/// Offset of the I/O Base Upper 16 Bits register.
+pub const IO_BASE_UPPER_OFFSET: usize = 0x30;
+/// Offset of the I/O Limit Upper 16 Bits register.
+pub const IO_LIMIT_UPPER_OFFSET: usize = 0x32;
+/// Offset of the Capabilities Pointer.
+pub const CAPABILITIES_POINTER_OFFSET: usize = 0x34;
+/// Offset of the Expansion ROM Base Address register.
+pub const ROM_BAR_OFFSET: usize = 0x38;
+/// Offset of the Interrupt Line.
+pub const INTERRUPT_LINE_OFFSET: usize = 0x3C;
+/// Offset of the Interrupt Pin.
+pub const INTERRUPT_PIN_OFFSET: usize = 0x3D;
+/// Offset of the Bridge Control register.
+pub const BRIDGE_CONTROL_OFFSET: usize = 0x3E;
+
+// The header-specific fields follow the common header, in order, without
+// overlapping.
+const _: () = assert!(BARS_START_OFFSET == BIST_OFFSET + 1);
+const _: () = assert!(BARS_START_OFFSET + NUM_BARS * 4 <= PRIMARY_BUS_OFFSET);
+const _: () = assert!(SECONDARY_STATUS_OFFSET + 2 <= MEMORY_BASE_OFFSET);
+const _: () = assert!(IO_LIMIT_UPPER_OFFSET + 2 <= CAPABILITIES_POINTER_OFFSET);
+const _: () = assert!(CAPABILITIES_POINTER_OFFSET < ROM_BAR_OFFSET);
+const _: () = assert!(ROM_BAR_OFFSET + 4 <= INTERRUPT_LINE_OFFSET);
+const _: () = assert!(BRIDGE_CONTROL_OFFSET + 2 <= CAPABILITIES_START_OFFSET);
+
+/// Accessors for the fields of a type 1 configuration header.
+pub trait PciBridgeConfig: PciConfig {
+    /// Sets up the BAR, or pair of BARs, described by `config`.
+    ///
+    /// This works like
+    /// [`PciDeviceConfig::add_bar`](../device/trait.PciDeviceConfig.html#tymethod.add_bar),
+    /// but a type 1 header only has `NUM_BARS` BARs: BARs at higher indices
+    /// fail with `BarIndex`, and a 64-bit BAR can only start at index 0.
+    fn add_bar(&mut self, config: PciBarConfig) -> bar::Result<()>;
+
+    /// Returns the base address programmed in the BAR at `idx`.
+    ///
+    /// For a 64-bit BAR, `idx` must be the index of the lower half of the
+    /// pair.
+    fn bar_address(&self, idx: BridgeBarIndex) -> bar::Result<u64> {
+        bar::read_bar_address(self, BARS_START_OFFSET, NUM_BARS, idx.get())
+    }
+
+    /// Returns the range of bus numbers behind the bridge, from the Secondary
+    /// Bus Number to the Subordinate Bus Number.
+    ///
+    /// Config accesses to these buses are forwarded downstream by the bridge.
+    /// Fails if the guest programmed a secondary bus above the subordinate
+    /// one.
+    fn bus_range(&self) -> Result<RangeInclusive<u8>> {
+        let secondary = self.read_byte(SECONDARY_BUS_OFFSET)?;
+        let subordinate = self.read_byte(SUBORDINATE_BUS_OFFSET)?;
+        if secondary > subordinate {
+            return Err(Error::BusRangeInvalid(secondary, subordinate));
+        }
+        Ok(secondary..=subordinate)
+    }
+
+    /// Returns the base address programmed in the Expansion ROM BAR.
+    fn rom_base_address(&self) -> Result<u32> {
+        bar::read_rom_base_address(self, ROM_BAR_OFFSET)
+    }
+
+    /// Programs the base address of the Expansion ROM BAR, leaving the enable
+    /// bit untouched.
+    fn write_rom_base_address(&mut self, addr: u32) -> Result<()> {
+        bar::write_rom_base_address(self, ROM_BAR_OFFSET, addr)
+    }
+
+    /// Returns whether the Expansion ROM BAR enable bit is set, in which case
+    /// the ROM is mapped at its base address.
+    fn rom_enabled(&self) -> Result<bool> {
+        bar::read_rom_enabled(self, ROM_BAR_OFFSET)
+    }
+
+    /// Sets or clears the Expansion ROM BAR enable bit, leaving the base
+    /// address untouched.
+    fn set_rom_enable(&mut self, enable: bool) -> Result<()> {
+        bar::write_rom_enable(self, ROM_BAR_OFFSET, enable)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bar::{BarIndex, PciBarPrefetchable, PciBarRegion};
+    use crate::config_space::ConfigSpace;
+    use crate::device;
+
+    #[test]
+    fn test_rom_base_address() {
+        let mut config = ConfigSpace::new();
+        config.write_rom_base_address(0xFEB0_0000).unwrap();
+        assert_eq!(config.rom_base_address().unwrap(), 0xFEB0_0000);
+        assert_eq!(
+            config.read_register(ROM_BAR_OFFSET / 4).unwrap(),
+            0xFEB0_0000
+        );
+        assert_eq!(config.read_register(device::ROM_BAR_OFFSET / 4).unwrap(), 0);
+    }
+
+    #[test]
+    fn test_add_bar_limit() {
+        let bar = |idx| PciBarConfig {
+            index: BarIndex::new(idx).unwrap(),
+            region: PciBarRegion::new_32bit_mem_region(0x1000_0000 * idx as u32, 0x1000).unwrap(),
+            prefetchable: PciBarPrefetchable::NotPrefetchable,
+            fixed: false,
+        };
+        let mut config = ConfigSpace::new();
+        config.add_bar(bar(0)).unwrap();
+        config.add_bar(bar(1)).unwrap();
+        assert_eq!(config.add_bar(bar(2)), Err(bar::Error::BarIndex(2)));
+    }
+}
@@ -0,0 +1,598 @@
+//! PCI capabilities.
+//!
+//! Capabilities form a singly linked list in the configuration space, headed
+//! by the Capabilities Pointer of the header. Every capability starts with an
+//! ID byte followed by the offset of the next capability.
+//!
+//! Byte images of capabilities used throughout the crate start at the ID
+//! byte, so the offsets of the capability fields match the ones of the PCI
+//! specification.
+
+use crate::compact_pci_hot_swap::CompactPciHotSwapCap;
+use crate::device::CAPABILITIES_POINTER_OFFSET;
+use crate::hypertransport::HyperTransportCap;
+use crate::msi::{self, MsiCap};
+use crate::msix::MsiXCap;
+use crate::pci_config::{
+    Error, PciConfig, Result, PCI_CONFIG_SPACE_SIZE, STATUS_CAPABILITIES_LIST, STATUS_OFFSET,
+};
+use crate::pci_express::PciExpressCap;
+use crate::power_management::PowerManagementCap;
+
+/// Offset of the first byte available to capabilities.
+pub const CAPABILITIES_START_OFFSET: usize = 0x40;
+/// Maximum number of capabilities that fit in the standard configuration space.
+pub const MAX_CAPABILITIES: usize = (PCI_CONFIG_SPACE_SIZE - CAPABILITIES_START_OFFSET) / 4;
+
+/// Offset of the next pointer within a capability.
+pub(crate) const NEXT_POINTER_OFFSET: usize = 1;
+/// Bits of the capability pointers that are reserved, capabilities being
+/// dword aligned.
+const POINTER_RESERVED_BITS: u8 = 0x3;
+/// Capability pointer value some producers end the list with, instead of 0.
+const POINTER_END_MARKER: u8 = 0xFF;
+/// Offset of the length byte within a vendor specific capability.
+const VENDOR_LENGTH_OFFSET: usize = 2;
+
+/// Capability IDs.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum PciCapabilityId {
+    /// Null capability.
+    Null,
+    /// PCI Power Management.
+    PowerManagement,
+    /// Accelerated Graphics Port.
+    AcceleratedGraphicsPort,
+    /// Vital Product Data.
+    VitalProductData,
+    /// Slot Identification.
+    SlotIdentification,
+    /// Message Signaled Interrupts.
+    Msi,
+    /// CompactPCI Hot Swap.
+    CompactPciHotSwap,
+    /// PCI-X.
+    PciX,
+    /// HyperTransport.
+    HyperTransport,
+    /// Vendor specific.
+    VendorSpecific,
+    /// Debug port.
+    DebugPort,
+    /// CompactPCI central resource control.
+    CompactPciCentralResourceControl,
+    /// PCI Standard Hot-Plug Controller.
+    PciStandardHotPlugController,
+    /// Bridge Subsystem Vendor ID.
+    BridgeSubsystemVendorId,
+    /// AGP target PCI-to-PCI bridge.
+    AgpTargetPciToPciBridge,
+    /// Secure Device.
+    SecureDevice,
+    /// PCI Express.
+    PciExpress,
+    /// MSI-X.
+    MsiX,
+    /// Serial ATA Data/Index Configuration.
+    SataDataIndexConfiguration,
+    /// Advanced Features.
+    AdvancedFeatures,
+    /// Enhanced Allocation.
+    EnhancedAllocation,
+    /// Flattening Portal Bridge.
+    FlatteningPortalBridge,
+    /// ID not defined by the specification.
+    Unknown(u8),
+}
+
+impl From<u8> for PciCapabilityId {
+    fn from(id: u8) -> Self {
+        match id {
+            0x00 => PciCapabilityId::Null,
+            0x01 => PciCapabilityId::PowerManagement,
+            0x02 => PciCapabilityId::AcceleratedGraphicsPort,
+            0x03 => PciCapabilityId::VitalProductData,
+            0x04 => PciCapabilityId::SlotIdentification,
+            0x05 => PciCapabilityId::Msi,
+            0x06 => PciCapabilityId::CompactPciHotSwap,
+            0x07 => PciCapabilityId::PciX,
+            0x08 => PciCapabilityId::HyperTransport,
+            0x09 => PciCapabilityId::VendorSpecific,
+            0x0A => PciCapabilityId::DebugPort,
+            0x0B => PciCapabilityId::CompactPciCentralResourceControl,
+            0x0C => PciCapabilityId::PciStandardHotPlugController,
+            0x0D => PciCapabilityId::BridgeSubsystemVendorId,
+            0x0E => PciCapabilityId::AgpTargetPciToPciBridge,
+            0x0F => PciCapabilityId::SecureDevice,
+            0x10 => PciCapabilityId::PciExpress,
+            0x11 => PciCapabilityId::MsiX,
+            0x12 => PciCapabilityId::SataDataIndexConfiguration,
+            0x13 => PciCapabilityId::AdvancedFeatures,
+            0x14 => PciCapabilityId::EnhancedAllocation,
+            0x15 => PciCapabilityId::FlatteningPortalBridge,
+            id => PciCapabilityId::Unknown(id),
+        }
+    }
+}
+
+impl From<PciCapabilityId> for u8 {
+    fn from(id: PciCapabilityId) -> Self {
+        match id {
+            PciCapabilityId::Null => 0x00,
+            PciCapabilityId::PowerManagement => 0x01,
+            PciCapabilityId::AcceleratedGraphicsPort => 0x02,
+            PciCapabilityId::VitalProductData => 0x03,
+            PciCapabilityId::SlotIdentification => 0x04,
+            PciCapabilityId::Msi => 0x05,
+            PciCapabilityId::CompactPciHotSwap => 0x06,
+            PciCapabilityId::PciX => 0x07,
+            PciCapabilityId::HyperTransport => 0x08,
+            PciCapabilityId::VendorSpecific => 0x09,
+            PciCapabilityId::DebugPort => 0x0A,
+            PciCapabilityId::CompactPciCentralResourceControl => 0x0B,
+            PciCapabilityId::PciStandardHotPlugController => 0x0C,
+            PciCapabilityId::BridgeSubsystemVendorId => 0x0D,
+            PciCapabilityId::AgpTargetPciToPciBridge => 0x0E,
+            PciCapabilityId::SecureDevice => 0x0F,
+            PciCapabilityId::PciExpress => 0x10,
+            PciCapabilityId::MsiX => 0x11,
+            PciCapabilityId::SataDataIndexConfiguration => 0x12,
+            PciCapabilityId::AdvancedFeatures => 0x13,
+            PciCapabilityId::EnhancedAllocation => 0x14,
+            PciCapabilityId::FlatteningPortalBridge => 0x15,
+            PciCapabilityId::Unknown(id) => id,
+        }
+    }
+}
+
+/// Location of a capability in the configuration space.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct CapabilityRegion {
+    /// ID of the capability.
+    pub id: PciCapabilityId,
+    /// Offset of the capability in the configuration space.
+    pub offset: usize,
+}
+
+/// Capability that can be installed in a configuration space.
+///
+/// Capabilities the crate doesn't model are best described with a
+/// [`CapabilityBuilder`](struct.CapabilityBuilder.html), which lays out their
+/// fields in little-endian order.
+pub trait PciCapability {
+    /// Returns the ID of the capability.
+    fn id(&self) -> PciCapabilityId;
+
+    /// Returns the byte image of the capability, starting at the ID byte.
+    ///
+    /// The ID and next pointer bytes are filled in when the capability is
+    /// installed.
+    fn to_bytes(&self) -> Vec<u8>;
+}
+
+/// Capability made of raw bytes, as built by a
+/// [`CapabilityBuilder`](struct.CapabilityBuilder.html).
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RawCapability {
+    /// ID of the capability.
+    pub id: PciCapabilityId,
+    /// Bytes of the capability, starting at the ID byte.
+    pub bytes: Vec<u8>,
+}
+
+impl PciCapability for RawCapability {
+    fn id(&self) -> PciCapabilityId {
+        self.id
+    }
+
+    fn to_bytes(&self) -> Vec<u8> {
+        self.bytes.clone()
+    }
+}
+
+/// Builder laying out the fields of a capability in little-endian order.
+///
+/// The fields are appended after the ID and next pointer bytes, so that the
+/// first one lands at offset 2 of the capability.
+#[derive(Clone, Debug)]
+pub struct CapabilityBuilder {
+    id: PciCapabilityId,
+    bytes: Vec<u8>,
+}
+
+impl CapabilityBuilder {
+    /// Starts a capability with the given `id`.
+    pub fn new(id: PciCapabilityId) -> Self {
+        let mut bytes = vec![0; NEXT_POINTER_OFFSET + 1];
+        bytes[0] = u8::from(id);
+        CapabilityBuilder { id, bytes }
+    }
+
+    /// Appends a byte field.
+    pub fn push_u8(mut self, value: u8) -> Self {
+        self.bytes.push(value);
+        self
+    }
+
+    /// Appends a 16-bit field.
+    pub fn push_u16(mut self, value: u16) -> Self {
+        self.bytes.extend_from_slice(&value.to_le_bytes());
+        self
+    }
+
+    /// Appends a 32-bit field.
+    pub fn push_u32(mut self, value: u32) -> Self {
+        self.bytes.extend_from_slice(&value.to_le_bytes());
+        self
+    }
+
+    /// Returns the capability.
+    pub fn build(self) -> RawCapability {
+        RawCapability {
+            id: self.id,
+            bytes: self.bytes,
+        }
+    }
+}
+
+/// Vendor specific capability.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct VendorCap {
+    /// Vendor defined bytes following the length byte.
+    pub data: Vec<u8>,
+}
+
+impl PciCapability for VendorCap {
+    fn id(&self) -> PciCapabilityId {
+        PciCapabilityId::VendorSpecific
+    }
+
+    fn to_bytes(&self) -> Vec<u8> {
+        let len = VENDOR_LENGTH_OFFSET + 1 + self.data.len();
+        let mut bytes = vec![0; VENDOR_LENGTH_OFFSET + 1];
+        bytes[VENDOR_LENGTH_OFFSET] = len as u8;
+        bytes.extend_from_slice(&self.data);
+        bytes
+    }
+}
+
+/// Capability decoded from the configuration space.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ParsedCapability {
+    /// Power Management capability.
+    PowerManagement(PowerManagementCap),
+    /// MSI capability.
+    Msi(MsiCap),
+    /// MSI-X capability.
+    MsiX(MsiXCap),
+    /// PCI Express capability.
+    PciExpress(PciExpressCap),
+    /// Vendor specific capability.
+    VendorSpecific(VendorCap),
+    /// CompactPCI Hot Swap capability.
+    CompactPciHotSwap(CompactPciHotSwapCap),
+    /// HyperTransport capability, whose data extends up to the next
+    /// capability in the configuration space or the end of the standard
+    /// configuration space.
+    HyperTransport(HyperTransportCap),
+    /// Capability the crate doesn't decode.
+    Raw {
+        /// ID of the capability.
+        id: PciCapabilityId,
+        /// Bytes of the capability, up to the next capability in the
+        /// configuration space or the end of the standard configuration space.
+        bytes: Vec<u8>,
+    },
+}
+
+pub(crate) fn read_u16(bytes: &[u8], offset: usize) -> u16 {
+    u16::from_le_bytes([bytes[offset], bytes[offset + 1]])
+}
+
+pub(crate) fn write_u16(bytes: &mut [u8], offset: usize, value: u16) {
+    bytes[offset..offset + 2].copy_from_slice(&value.to_le_bytes());
+}
+
+pub(crate) fn write_u32(bytes: &mut [u8], offset: usize, value: u32) {
+    bytes[offset..offset + 4].copy_from_slice(&value.to_le_bytes());
+}
+
+pub(crate) fn read_u32(bytes: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes([
+        bytes[offset],
+        bytes[offset + 1],
+        bytes[offset + 2],
+        bytes[offset + 3],
+    ])
+}
+
+// Reads the `len` bytes of the capability at `offset`.
+fn read_cap_bytes<C: PciConfig + ?Sized>(cfg: &C, offset: usize, len: usize) -> Result<Vec<u8>> {
+    check_fits(offset, len)?;
+    cfg.capability_bytes(offset, len)
+}
+
+// Reads the `len` bytes of the capability at `offset`, failing with
+// `CapabilityIdMismatch` unless its ID is `id`.
+pub(crate) fn read_capability<C: PciConfig + ?Sized>(
+    cfg: &C,
+    offset: usize,
+    id: PciCapabilityId,
+    len: usize,
+) -> Result<Vec<u8>> {
+    if cfg.capability_id_at(offset)? != id {
+        return Err(Error::CapabilityIdMismatch(offset));
+    }
+    read_cap_bytes(cfg, offset, len)
+}
+
+// Visits the capabilities in list order until `f` returns `false`.
+//
+// The reserved low bits of the pointers are ignored. Besides 0, producers
+// terminate the list with 0xFF or with a pointer into the header, which are
+// taken as the end of the list too. Fails with `CapabilityListCorrupt` if a
+// next pointer loops back to an already visited capability.
+fn visit<C, F>(cfg: &C, mut f: F) -> Result<()>
+where
+    C: PciConfig + ?Sized,
+    F: FnMut(CapabilityRegion) -> bool,
+{
+    if cfg.read_word(STATUS_OFFSET)? & STATUS_CAPABILITIES_LIST == 0 {
+        return Ok(());
+    }
+
+    let mut visited = [false; PCI_CONFIG_SPACE_SIZE];
+    let mut pointer_offset = CAPABILITIES_POINTER_OFFSET;
+    let mut offset = read_pointer(cfg, pointer_offset)?;
+    while offset != 0 {
+        if visited[offset] {
+            return Err(Error::CapabilityListCorrupt(pointer_offset));
+        }
+        visited[offset] = true;
+        check_fits(offset, NEXT_POINTER_OFFSET + 1)?;
+
+        let id = cfg.capability_id_at(offset)?;
+        if !f(CapabilityRegion { id, offset }) {
+            break;
+        }
+        pointer_offset = offset + NEXT_POINTER_OFFSET;
+        offset = read_pointer(cfg, pointer_offset)?;
+    }
+    Ok(())
+}
+
+// Reads the pointer at `pointer_offset`, returning 0 for any end of list
+// marker.
+fn read_pointer<C: PciConfig + ?Sized>(cfg: &C, pointer_offset: usize) -> Result<usize> {
+    let pointer = cfg.read_byte(pointer_offset)?;
+    if pointer == POINTER_END_MARKER {
+        return Ok(0);
+    }
+    let offset = usize::from(pointer & !POINTER_RESERVED_BITS);
+    if offset < CAPABILITIES_START_OFFSET {
+        return Ok(0);
+    }
+    Ok(offset)
+}
+
+// Checks that a capability of `len` bytes at `offset` fits in the standard
+// configuration space.
+fn check_fits(offset: usize, len: usize) -> Result<()> {
+    if offset + len > PCI_CONFIG_SPACE_SIZE {
+        return Err(Error::CapabilityListCorrupt(offset));
+    }
+    Ok(())
+}
+
+pub(crate) fn walk<C: PciConfig + ?Sized>(cfg: &C) -> Result<Vec<CapabilityRegion>> {
+    let mut regions = Vec::new();
+    visit(cfg, |region| {
+        regions.push(region);
+        true
+    })?;
+    Ok(regions)
+}
+
+pub(crate) fn walk_into<C: PciConfig + ?Sized>(
+    cfg: &C,
+    buf: &mut [CapabilityRegion],
+) -> Result<usize> {
+    let mut count = 0;
+    visit(cfg, |region| {
+        if let Some(slot) = buf.get_mut(count) {
+            *slot = region;
+        }
+        count += 1;
+        true
+    })?;
+    if count > buf.len() {
+        return Err(Error::CapabilityBufferTooSmall(count));
+    }
+    Ok(count)
+}
+
+pub(crate) fn gaps<C: PciConfig + ?Sized>(cfg: &C) -> Result<Vec<(usize, usize)>> {
+    let regions = walk(cfg)?;
+    let mut used = Vec::with_capacity(regions.len());
+    for region in regions.iter() {
+        used.push((region.offset, length(cfg, region, &regions)?));
+    }
+    used.sort_unstable();
+
+    let mut gaps = Vec::new();
+    let mut start = CAPABILITIES_START_OFFSET;
+    for (offset, len) in used {
+        if offset > start {
+            gaps.push((start, offset - start));
+        }
+        start = start.max(offset + len);
+    }
+    if start < PCI_CONFIG_SPACE_SIZE {
+        gaps.push((start, PCI_CONFIG_SPACE_SIZE - start));
+    }
+    Ok(gaps)
+}
+
+pub(crate) fn find<C: PciConfig + ?Sized>(cfg: &C, id: PciCapabilityId) -> Result<Option<usize>> {
+    let mut found = None;
+    visit(cfg, |region| {
+        if region.id == id {
+            found = Some(region.offset);
+        }
+        found.is_none()
+    })?;
+    Ok(found)
+}
+
+// Returns the length of the capability at `region`, out of the capabilities
+// in `regions`.
+//
+// Capabilities the crate doesn't know are assumed to extend up to the next
+// capability in the configuration space, or to the end of the standard
+// configuration space.
+pub(crate) fn length<C: PciConfig + ?Sized>(
+    cfg: &C,
+    region: &CapabilityRegion,
+    regions: &[CapabilityRegion],
+) -> Result<usize> {
+    let offset = region.offset;
+    let len = match region.id {
+        PciCapabilityId::PowerManagement => PowerManagementCap::LEN,
+        PciCapabilityId::Msi => {
+            let header = read_cap_bytes(cfg, offset, msi::CONTROL_OFFSET + 2)?;
+            MsiCap::len_for_control(read_u16(&header, msi::CONTROL_OFFSET))
+        }
+        PciCapabilityId::MsiX => MsiXCap::LEN,
+        PciCapabilityId::PciExpress => PciExpressCap::LEN,
+        PciCapabilityId::CompactPciHotSwap => CompactPciHotSwapCap::LEN,
+        PciCapabilityId::VendorSpecific => {
+            let header = read_cap_bytes(cfg, offset, VENDOR_LENGTH_OFFSET + 1)?;
+            usize::from(header[VENDOR_LENGTH_OFFSET]).max(VENDOR_LENGTH_OFFSET + 1)
+        }
+        _ => {
+            let end = regions
+                .iter()
+                .map(|r| r.offset)
+                .filter(|&o| o > offset)
+                .min()
+                .unwrap_or(PCI_CONFIG_SPACE_SIZE);
+            end.saturating_sub(offset)
+        }
+    };
+    Ok(len)
+}
+
+pub(crate) fn parse<C: PciConfig + ?Sized>(cfg: &C) -> Result<Vec<ParsedCapability>> {
+    let regions = walk(cfg)?;
+    let mut parsed = Vec::with_capacity(regions.len());
+    for region in regions.iter() {
+        let len = length(cfg, region, &regions)?;
+        let bytes = read_cap_bytes(cfg, region.offset, len)?;
+        let cap = match region.id {
+            PciCapabilityId::PowerManagement => {
+                ParsedCapability::PowerManagement(PowerManagementCap::from_bytes(&bytes))
+            }
+            PciCapabilityId::Msi => ParsedCapability::Msi(MsiCap::from_bytes(&bytes)),
+            PciCapabilityId::MsiX => ParsedCapability::MsiX(MsiXCap::from_bytes(&bytes)),
+            PciCapabilityId::PciExpress => {
+                ParsedCapability::PciExpress(PciExpressCap::from_bytes(&bytes))
+            }
+            PciCapabilityId::VendorSpecific => ParsedCapability::VendorSpecific(VendorCap {
+                data: bytes[VENDOR_LENGTH_OFFSET + 1..].to_vec(),
+            }),
+            PciCapabilityId::CompactPciHotSwap => {
+                ParsedCapability::CompactPciHotSwap(CompactPciHotSwapCap::from_bytes(&bytes))
+            }
+            PciCapabilityId::HyperTransport => {
+                ParsedCapability::HyperTransport(HyperTransportCap::from_bytes(&bytes))
+            }
+            id => ParsedCapability::Raw { id, bytes },
+        };
+        parsed.push(cap);
+    }
+    Ok(parsed)
+}
+
+pub(crate) fn report<C: PciConfig + ?Sized>(cfg: &C) -> Result<String> {
+    let regions = walk(cfg)?;
+    let parsed = parse(cfg)?;
+    let mut report = String::new();
+    for (region, cap) in regions.iter().zip(parsed.iter()) {
+        let details = match cap {
+            ParsedCapability::PowerManagement(pm) => {
+                format!(", power state {:?}", pm.power_state())
+            }
+            ParsedCapability::Msi(msi) => format!(
+                ", 64-bit {}, per-vector masking {}",
+                msi.msg_ctl & msi::CONTROL_64BIT != 0,
+                msi.msg_ctl & msi::CONTROL_PER_VECTOR_MASK != 0
+            ),
+            ParsedCapability::MsiX(msix) => format!(", table size {}", msix.table_size()),
+            ParsedCapability::PciExpress(pcie) => {
+                format!(", port type {:#x}", pcie.device_port_type())
+            }
+            ParsedCapability::VendorSpecific(vendor) => {
+                format!(", {} bytes of data", vendor.data.len())
+            }
+            ParsedCapability::CompactPciHotSwap(hot_swap) => {
+                format!(", ENUM# asserted {}", hot_swap.enum_asserted())
+            }
+            ParsedCapability::HyperTransport(ht) => format!(", type {:?}", ht.cap_type()),
+            ParsedCapability::Raw { .. } => String::new(),
+        };
+        report.push_str(&format!(
+            "{:#04x}: {:?}{}\n",
+            region.offset, region.id, details
+        ));
+    }
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config_space::ConfigSpace;
+
+    // Returns a configuration space holding a Power Management capability at
+    // 0x40 followed by an MSI capability at 0x48.
+    fn two_caps() -> ConfigSpace {
+        let mut config = ConfigSpace::minimal(0x1234, 0x5678, 0x02, 0x00);
+        let pm = PowerManagementCap::default();
+        let msi = MsiCap::default();
+        config.install_capabilities(&[&pm, &msi]).unwrap();
+        config
+    }
+
+    fn offsets(config: &ConfigSpace) -> Vec<usize> {
+        walk(config).unwrap().iter().map(|r| r.offset).collect()
+    }
+
+    #[test]
+    fn test_pointer_reserved_bits() {
+        let mut config = two_caps();
+        config
+            .write_byte(CAPABILITIES_POINTER_OFFSET, 0x43)
+            .unwrap();
+        config.write_byte(0x40 + NEXT_POINTER_OFFSET, 0x4B).unwrap();
+        assert_eq!(offsets(&config), vec![0x40, 0x48]);
+
+        assert_eq!(
+            config.set_capability_writable(0x42, 4, &[]),
+            Err(Error::UnalignedAccess(0x42))
+        );
+    }
+
+    #[test]
+    fn test_list_terminators() {
+        let mut config = two_caps();
+        let last_next = 0x48 + NEXT_POINTER_OFFSET;
+        assert_eq!(config.read_byte(last_next).unwrap(), 0x00);
+        assert_eq!(offsets(&config), vec![0x40, 0x48]);
+
+        config.write_byte(last_next, 0xFF).unwrap();
+        assert_eq!(offsets(&config), vec![0x40, 0x48]);
+
+        config.write_byte(last_next, 0x20).unwrap();
+        assert_eq!(offsets(&config), vec![0x40, 0x48]);
+    }
+}
@@ -0,0 +1,57 @@
+//! Type 2 (PCI-to-CardBus bridge) configuration header.
+//!
+//! Only the layout of the header is described; the CardBus bridge behavior is
+//! not emulated.
+
+use crate::pci_config::BIST_OFFSET;
+
+/// Offset of the CardBus Socket/ExCa Base Address register.
+pub const SOCKET_BASE_OFFSET: usize = 0x10;
+/// Offset of the Capabilities Pointer.
+pub const CAPABILITIES_POINTER_OFFSET: usize = 0x14;
+/// Offset of the Secondary Status register.
+pub const SECONDARY_STATUS_OFFSET: usize = 0x16;
+/// Offset of the PCI Bus Number.
+pub const PCI_BUS_OFFSET: usize = 0x18;
+/// Offset of the CardBus Bus Number.
+pub const CARDBUS_BUS_OFFSET: usize = 0x19;
+/// Offset of the Subordinate Bus Number.
+pub const SUBORDINATE_BUS_OFFSET: usize = 0x1A;
+/// Offset of the CardBus Latency Timer.
+pub const CARDBUS_LATENCY_TIMER_OFFSET: usize = 0x1B;
+/// Offset of the Memory Base Address 0 register.
+pub const MEMORY_BASE_0_OFFSET: usize = 0x1C;
+/// Offset of the Memory Limit 0 register.
+pub const MEMORY_LIMIT_0_OFFSET: usize = 0x20;
+/// Offset of the Memory Base Address 1 register.
+pub const MEMORY_BASE_1_OFFSET: usize = 0x24;
+/// Offset of the Memory Limit 1 register.
+pub const MEMORY_LIMIT_1_OFFSET: usize = 0x28;
+/// Offset of the I/O Base Address 0 register.
+pub const IO_BASE_0_OFFSET: usize = 0x2C;
+/// Offset of the I/O Limit 0 register.
+pub const IO_LIMIT_0_OFFSET: usize = 0x30;
+/// Offset of the I/O Base Address 1 register.
+pub const IO_BASE_1_OFFSET: usize = 0x34;
+/// Offset of the I/O Limit 1 register.
+pub const IO_LIMIT_1_OFFSET: usize = 0x38;
+/// Offset of the Interrupt Line.
+pub const INTERRUPT_LINE_OFFSET: usize = 0x3C;
+/// Offset of the Interrupt Pin.
+pub const INTERRUPT_PIN_OFFSET: usize = 0x3D;
+/// Offset of the Bridge Control register.
+pub const BRIDGE_CONTROL_OFFSET: usize = 0x3E;
+/// Offset of the Subsystem Device ID.
+pub const SUBSYSTEM_ID_OFFSET: usize = 0x40;
+/// Offset of the Subsystem Vendor ID.
+pub const SUBSYSTEM_VENDOR_ID_OFFSET: usize = 0x42;
+/// Offset of the 16-bit PC Card Legacy Mode Base Address register.
+pub const LEGACY_MODE_BASE_OFFSET: usize = 0x44;
+/// Size of the type 2 header.
+pub const HEADER_SIZE: usize = 0x48;
+
+// The header-specific fields follow the common header, in order, without
+// overlapping.
+const _: () = assert!(SOCKET_BASE_OFFSET == BIST_OFFSET + 1);
+const _: () = assert!(IO_LIMIT_1_OFFSET + 4 <= INTERRUPT_LINE_OFFSET);
+const _: () = assert!(LEGACY_MODE_BASE_OFFSET + 4 == HEADER_SIZE);
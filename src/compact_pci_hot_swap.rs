@@ -0,0 +1,97 @@
+//! CompactPCI Hot Swap capability.
+//!
+//! The capability holds the Hot Swap Control/Status register, through which
+//! the system software learns that a board was inserted or is about to be
+//! extracted. The board signals these events on ENUM# and latches them in
+//! the INS and EXT status bits, which the software clears by writing 1.
+
+use crate::capability::{read_u16, write_u16, PciCapability, PciCapabilityId};
+
+/// Offset of the Hot Swap Control/Status register.
+pub const CSR_OFFSET: usize = 0x02;
+
+/// Control/Status bit arming the hiding of the device from the bus.
+pub const CSR_DEVICE_HIDING_ARM: u16 = 1 << 0;
+/// Control/Status bit masking the ENUM# signal.
+pub const CSR_ENUM_MASK: u16 = 1 << 1;
+/// Control/Status bit set while an insertion or extraction is pending.
+pub const CSR_PENDING: u16 = 1 << 2;
+/// Control/Status bit turning the blue LED on.
+pub const CSR_LED_ON: u16 = 1 << 3;
+/// Control/Status bits holding the programming interface.
+pub const CSR_PROGRAMMING_INTERFACE_MASK: u16 = 0x3 << 4;
+/// Control/Status bit set once the board has been extracted, cleared by
+/// writing 1.
+pub const CSR_EXTRACTION: u16 = 1 << 6;
+/// Control/Status bit set once the board has been inserted, cleared by
+/// writing 1.
+pub const CSR_INSERTION: u16 = 1 << 7;
+
+// Control/Status bits the software can write.
+const CSR_WRITABLE: u16 = CSR_DEVICE_HIDING_ARM | CSR_ENUM_MASK | CSR_LED_ON;
+// Control/Status bits the software clears by writing 1.
+const CSR_WRITE_1_TO_CLEAR: u16 = CSR_EXTRACTION | CSR_INSERTION;
+
+/// CompactPCI Hot Swap capability.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct CompactPciHotSwapCap {
+    /// Hot Swap Control/Status register.
+    pub csr: u16,
+}
+
+impl CompactPciHotSwapCap {
+    /// ID of the capability.
+    pub const ID: PciCapabilityId = PciCapabilityId::CompactPciHotSwap;
+    /// Length of the capability in bytes.
+    pub const LEN: usize = 4;
+
+    /// Applies a software write of `value` to the Control/Status register.
+    ///
+    /// The mask, LED and hiding control bits take the written value, the INS
+    /// and EXT status bits are cleared where `value` has a 1, and the other
+    /// bits are read-only.
+    pub fn write_csr(&mut self, value: u16) {
+        let status = self.csr & CSR_WRITE_1_TO_CLEAR & !value;
+        let read_only = self.csr & !(CSR_WRITABLE | CSR_WRITE_1_TO_CLEAR);
+        self.csr = read_only | status | (value & CSR_WRITABLE);
+    }
+
+    /// Latches the insertion of the board in the INS status bit.
+    pub fn signal_insertion(&mut self) {
+        self.csr |= CSR_INSERTION;
+    }
+
+    /// Latches the upcoming extraction of the board in the EXT status bit.
+    pub fn signal_extraction(&mut self) {
+        self.csr |= CSR_EXTRACTION;
+    }
+
+    /// Returns whether the board asserts ENUM#: an insertion or extraction
+    /// is latched and ENUM# isn't masked.
+    pub fn enum_asserted(&self) -> bool {
+        self.csr & CSR_WRITE_1_TO_CLEAR != 0 && self.csr & CSR_ENUM_MASK == 0
+    }
+
+    /// Returns whether the blue LED is on.
+    pub fn led_on(&self) -> bool {
+        self.csr & CSR_LED_ON != 0
+    }
+
+    pub(crate) fn from_bytes(bytes: &[u8]) -> Self {
+        CompactPciHotSwapCap {
+            csr: read_u16(bytes, CSR_OFFSET),
+        }
+    }
+}
+
+impl PciCapability for CompactPciHotSwapCap {
+    fn id(&self) -> PciCapabilityId {
+        Self::ID
+    }
+
+    fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = vec![0; Self::LEN];
+        write_u16(&mut bytes, CSR_OFFSET, self.csr);
+        bytes
+    }
+}
@@ -0,0 +1,1066 @@
+//! Concrete configuration space backed by memory.
+
+use std::fmt;
+use std::ops::Range;
+
+use crate::bar::{self, BarIndex, PciBarConfig};
+use crate::bridge::{self, PciBridgeConfig};
+use crate::capability::{PciCapability, CAPABILITIES_START_OFFSET, NEXT_POINTER_OFFSET};
+use crate::device::{self, PciDeviceConfig};
+use crate::extended_capability::{self, PciExtendedCapability, EXTENDED_CAPABILITIES_START_OFFSET};
+use crate::pci_config::{
+    Error, PciConfig, PciHeaderType, Result, CACHE_LINE_SIZE_OFFSET, COMMAND_INTERRUPT_DISABLE,
+    COMMAND_IO_SPACE, COMMAND_MEMORY_SPACE, COMMAND_OFFSET, HEADER_TYPE_MULTIFUNCTION,
+    HEADER_TYPE_OFFSET, PCIE_CONFIG_SPACE_SIZE, PCI_CONFIG_SPACE_SIZE, REVISION_ID_OFFSET,
+    STATUS_CAPABILITIES_LIST, STATUS_INTERRUPT, STATUS_OFFSET, STATUS_WRITE_1_TO_CLEAR,
+    VENDOR_ID_OFFSET,
+};
+use crate::reset::{self, ResetKind, ResetPolicy};
+
+/// Problems found by [`ConfigSpace::finalize`](struct.ConfigSpace.html#method.finalize).
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SetupError {
+    /// The regions of the two BARs overlap.
+    BarOverlap(BarIndex, BarIndex),
+    /// The capability list is malformed.
+    CapabilityList(Error),
+}
+
+impl fmt::Display for SetupError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SetupError::BarOverlap(a, b) => {
+                write!(f, "BARs {} and {} overlap", a.get(), b.get())
+            }
+            SetupError::CapabilityList(e) => write!(f, "invalid capability list: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for SetupError {}
+
+// Register holding the Cache Line Size (byte 0) and the Latency Timer (byte 1).
+const CACHE_LINE_LATENCY_REG: usize = CACHE_LINE_SIZE_OFFSET / 4;
+// Register holding the Command and Status registers, and the Interrupt Status
+// bit within it.
+const STATUS_REG: usize = STATUS_OFFSET / 4;
+const STATUS_INTERRUPT_BIT: u32 = (STATUS_INTERRUPT as u32) << ((STATUS_OFFSET % 4) * 8);
+// Status bits the guest clears by writing 1.
+const STATUS_WRITE_1_TO_CLEAR_BITS: u32 =
+    (STATUS_WRITE_1_TO_CLEAR as u32) << ((STATUS_OFFSET % 4) * 8);
+
+/// Configuration space stored as an array of registers.
+///
+/// Writes through [`PciConfig`](../pci_config/trait.PciConfig.html) are the
+/// guest view of the configuration space: they only change the bits the
+/// device lets the guest write, such as the address bits of its BARs. The
+/// Header Type register is read-only to the guest, and so are the BARs that
+/// aren't set up once the configuration space is made a type 0 header. The
+/// error bits of the Status register are cleared by writing 1.
+///
+/// Cloning a `ConfigSpace` copies its registers, which makes for a cheap
+/// snapshot: the state can later be restored by assigning the clone back.
+/// The clone captures the whole state: the registers and the bits the guest
+/// can write, the BAR setup, the access modes, the INTx state and the
+/// device-specific region.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ConfigSpace {
+    registers: Vec<u32>,
+    write_masks: Vec<u32>,
+    bars: [Option<PciBarConfig>; device::NUM_BARS],
+    num_bars: usize,
+    pcie_mode: bool,
+    zero_fill: bool,
+    fixed_bar_sizing: bool,
+    intx_asserted: bool,
+    device_specific: Range<usize>,
+}
+
+impl ConfigSpace {
+    /// Creates a zeroed 256-byte PCI configuration space.
+    ///
+    /// The registers after the standard header fields stay writable, so that
+    /// the configuration space can back any header type. Setting up a type 0
+    /// header with [`minimal`](#method.minimal),
+    /// [`set_num_bars`](#method.set_num_bars) or
+    /// [`finalize`](#method.finalize) hardwires its unused BAR slots to 0.
+    pub fn new() -> Self {
+        Self::with_size(PCI_CONFIG_SPACE_SIZE)
+    }
+
+    /// Creates a zeroed 4 KiB PCI Express configuration space.
+    pub fn new_pcie() -> Self {
+        Self::with_size(PCIE_CONFIG_SPACE_SIZE)
+    }
+
+    /// Creates the smallest configuration space a guest can enumerate: a
+    /// single-function device with the given IDs and class, no BARs, no
+    /// capabilities and a cleared Command register.
+    ///
+    /// The Status register is left at 0, which is its reset value for such a
+    /// device: the Capabilities List bit is clear as there are no
+    /// capabilities, no error is latched and INTx isn't asserted.
+    /// `install_capabilities` and `finalize` set the Capabilities List bit
+    /// when capabilities are added.
+    ///
+    /// This is the recommended starting point for emulating a device, on top
+    /// of which BARs and capabilities are added.
+    pub fn minimal(vendor_id: u16, device_id: u16, class: u8, subclass: u8) -> Self {
+        let mut config = Self::new();
+        config.registers[VENDOR_ID_OFFSET / 4] =
+            u32::from(vendor_id) | (u32::from(device_id) << 16);
+        config.registers[REVISION_ID_OFFSET / 4] =
+            (u32::from(class) << 24) | (u32::from(subclass) << 16);
+        config.registers[HEADER_TYPE_OFFSET / 4] =
+            u32::from(u8::from(PciHeaderType::Device)) << ((HEADER_TYPE_OFFSET % 4) * 8);
+        config.hardwire_absent_bars();
+        config
+    }
+
+    fn with_size(size: usize) -> Self {
+        let mut config = ConfigSpace {
+            registers: vec![0; size / 4],
+            write_masks: vec![u32::MAX; size / 4],
+            bars: [None; device::NUM_BARS],
+            num_bars: device::NUM_BARS,
+            pcie_mode: false,
+            zero_fill: false,
+            fixed_bar_sizing: false,
+            intx_asserted: false,
+            device_specific: 0..0,
+        };
+        // The header type, multi-function bit included, is hardwired.
+        config.set_byte_writable(HEADER_TYPE_OFFSET, false);
+        // The Status register is driven by the device, the guest only clears
+        // its error bits.
+        config.write_masks[STATUS_REG] =
+            (config.write_masks[STATUS_REG] & 0xffff) | STATUS_WRITE_1_TO_CLEAR_BITS;
+        config
+    }
+
+    /// Limits the BARs of the device to the first `num_bars` ones.
+    ///
+    /// `add_bar` then rejects BARs at higher indices. A device without BARs,
+    /// such as a management function, sets 0. Fails if `num_bars` is larger
+    /// than `NUM_BARS` or if a BAR is already set up past the limit.
+    pub fn set_num_bars(&mut self, num_bars: usize) -> bar::Result<()> {
+        if num_bars > device::NUM_BARS {
+            return Err(bar::Error::BarIndex(num_bars));
+        }
+        if let Some(config) = self.bars().iter().find(|config| {
+            let len = if config.write_mask_high().is_some() {
+                2
+            } else {
+                1
+            };
+            config.index.get() + len > num_bars
+        }) {
+            return Err(bar::Error::BarIndex(config.index.get()));
+        }
+        self.num_bars = num_bars;
+        self.hardwire_absent_bars();
+        Ok(())
+    }
+
+    /// Enables or disables the PCI Express handling of legacy header fields.
+    ///
+    /// PCI Express functions hardwire the Latency Timer to 0 and don't let
+    /// the Cache Line Size be changed. In this mode, writes to the Latency
+    /// Timer are ignored and it reads as 0, and writes to the Cache Line Size
+    /// are ignored. The mode is disabled by default.
+    pub fn set_pcie_mode(&mut self, enabled: bool) {
+        self.pcie_mode = enabled;
+        if enabled {
+            self.registers[CACHE_LINE_LATENCY_REG] &= !0xff00;
+        }
+    }
+
+    /// Returns whether the PCI Express handling of legacy header fields is
+    /// enabled.
+    pub fn pcie_mode(&self) -> bool {
+        self.pcie_mode
+    }
+
+    /// Returns whether the configuration space implements the 4 KiB extended
+    /// configuration space of PCI Express functions.
+    ///
+    /// Extended capabilities can only be walked if it does. Zero-filling the
+    /// unimplemented registers doesn't make a configuration space PCI Express.
+    pub fn is_pcie(&self) -> bool {
+        self.registers.len() * 4 >= PCIE_CONFIG_SPACE_SIZE
+    }
+
+    /// Returns the index of the last implemented register.
+    pub fn max_register_index(&self) -> usize {
+        self.registers.len() - 1
+    }
+
+    /// Enables or disables the zero-filling of the unimplemented registers.
+    ///
+    /// In this mode, the configuration space behaves as if it were 4 KiB
+    /// long: the registers past its end, such as the extended configuration
+    /// space of a conventional function, read as 0 and ignore writes, as on
+    /// real hardware. Only accesses past 4 KiB fail. The mode is disabled by
+    /// default.
+    pub fn set_zero_fill(&mut self, enabled: bool) {
+        self.zero_fill = enabled;
+    }
+
+    /// Returns whether the unimplemented registers are zero-filled.
+    pub fn zero_fill(&self) -> bool {
+        self.zero_fill
+    }
+
+    /// Enables or disables the sizing of fixed BARs.
+    ///
+    /// By default, fixed BARs ignore every guest write, so a guest probing
+    /// their size reads back their address. In this mode, they behave like
+    /// relocatable BARs when the guest writes all ones to their address bits,
+    /// reading back the size mask, but any other write restores their fixed
+    /// address. This models devices whose BAR sizes are discoverable but which
+    /// are placed by the platform.
+    pub fn set_fixed_bar_sizing(&mut self, enabled: bool) {
+        self.fixed_bar_sizing = enabled;
+    }
+
+    /// Returns whether fixed BARs can be sized.
+    pub fn fixed_bar_sizing(&self) -> bool {
+        self.fixed_bar_sizing
+    }
+
+    // Returns the value of the register at `reg_idx` after the guest wrote
+    // `value` to it, if it belongs to a fixed BAR that can be sized.
+    fn fixed_bar_write(&self, reg_idx: usize, value: u32) -> Option<u32> {
+        if !self.fixed_bar_sizing {
+            return None;
+        }
+        let idx = reg_idx.checked_sub(device::BARS_START_OFFSET / 4)?;
+        let (config, high) = match self.bars.get(idx)? {
+            Some(config) => (config, false),
+            None => (self.bars.get(idx.checked_sub(1)?)?.as_ref()?, true),
+        };
+        if !config.fixed {
+            return None;
+        }
+        let (low, value_high) = bar::encode_registers(config);
+        let sizing = PciBarConfig {
+            fixed: false,
+            ..*config
+        };
+        let (fixed, mask) = if high {
+            (value_high?, sizing.write_mask_high()?)
+        } else {
+            (low, sizing.write_mask())
+        };
+        Some(if value & mask == mask {
+            (fixed & !mask) | mask
+        } else {
+            fixed
+        })
+    }
+
+    /// Asserts or deasserts the INTx interrupt of the device.
+    ///
+    /// The Interrupt Status bit of the Status register reflects this state,
+    /// even when the Interrupt Disable bit of the Command register is set.
+    /// Use [`intx_pending`](#method.intx_pending) to know whether the
+    /// interrupt must be delivered.
+    pub fn set_intx_asserted(&mut self, asserted: bool) {
+        self.intx_asserted = asserted;
+    }
+
+    /// Asserts the INTx interrupt of the device and returns whether it must
+    /// be delivered, as [`intx_pending`](#method.intx_pending) does.
+    pub fn signal_intx(&mut self) -> bool {
+        self.intx_asserted = true;
+        self.intx_pending()
+    }
+
+    /// Deasserts the INTx interrupt of the device and returns whether it was
+    /// being delivered, in which case the interrupt line must be lowered.
+    pub fn clear_intx(&mut self) -> bool {
+        let pending = self.intx_pending();
+        self.intx_asserted = false;
+        pending
+    }
+
+    /// Returns whether the INTx interrupt of the device is asserted.
+    pub fn intx_asserted(&self) -> bool {
+        self.intx_asserted
+    }
+
+    /// Returns whether the INTx interrupt must be delivered: it is asserted
+    /// and the guest didn't set the Interrupt Disable bit.
+    pub fn intx_pending(&self) -> bool {
+        let command = self.registers[COMMAND_OFFSET / 4] >> ((COMMAND_OFFSET % 4) * 8);
+        self.intx_asserted && command as u16 & COMMAND_INTERRUPT_DISABLE == 0
+    }
+
+    /// Restricts guest writes to the capability of `cap_len` bytes at
+    /// `cap_offset` to the `(offset, length)` byte ranges of `writable`,
+    /// which are relative to the capability start.
+    ///
+    /// Guest writes to the other bytes of the capability are ignored, as for
+    /// the device-managed fields of real hardware. Calling this again for the
+    /// same capability replaces its writable ranges. Capabilities are dword
+    /// aligned, so this fails with `UnalignedAccess` if `cap_offset` isn't.
+    pub fn set_capability_writable(
+        &mut self,
+        cap_offset: usize,
+        cap_len: usize,
+        writable: &[(usize, usize)],
+    ) -> Result<()> {
+        if cap_offset & 0x3 != 0 {
+            return Err(Error::UnalignedAccess(cap_offset));
+        }
+        let size = self.registers.len() * 4;
+        match cap_offset.checked_add(cap_len) {
+            Some(end) if end <= size => (),
+            _ => return Err(Error::OffsetOutOfBounds(cap_offset)),
+        }
+        if let Some((offset, _)) = writable
+            .iter()
+            .find(|(offset, len)| offset.checked_add(*len).map_or(true, |end| end > cap_len))
+        {
+            return Err(Error::OffsetOutOfBounds(cap_offset.saturating_add(*offset)));
+        }
+
+        for offset in cap_offset..cap_offset + cap_len {
+            self.set_byte_writable(offset, false);
+        }
+        for (offset, len) in writable {
+            for offset in cap_offset + offset..cap_offset + offset + len {
+                self.set_byte_writable(offset, true);
+            }
+        }
+        Ok(())
+    }
+
+    /// Installs `caps` as the capability list, in order, and returns the
+    /// offset of each capability.
+    ///
+    /// The capabilities are laid out one after the other from the end of the
+    /// header, each on a dword boundary, skipping the device-specific region.
+    /// The list is chained, the Capabilities Pointer points to its head and
+    /// the Capabilities List bit of the Status register tells whether it's
+    /// empty. This replaces any previous capability list: the registers from
+    /// the end of the header to the end of the standard configuration space,
+    /// except the device-specific region, are cleared and made writable
+    /// again, undoing `set_capability_writable`. The ID and next pointer of
+    /// each capability are then made read-only.
+    ///
+    /// Fails with `CapabilitiesOverflow` if the capabilities don't fit in the
+    /// standard configuration space, leaving the configuration space
+    /// untouched.
+    pub fn install_capabilities(&mut self, caps: &[&dyn PciCapability]) -> Result<Vec<usize>> {
+        let images: Vec<Vec<u8>> = caps
+            .iter()
+            .map(|cap| {
+                let mut bytes = cap.to_bytes();
+                if bytes.len() <= NEXT_POINTER_OFFSET {
+                    bytes.resize(NEXT_POINTER_OFFSET + 1, 0);
+                }
+                bytes[0] = u8::from(cap.id());
+                bytes
+            })
+            .collect();
+
+        let area = CAPABILITIES_START_OFFSET..PCI_CONFIG_SPACE_SIZE;
+        let offsets = self.lay_out_capabilities(area.clone(), &images)?;
+        self.clear_capability_area(area);
+        for (i, bytes) in images.iter().enumerate() {
+            let next = offsets.get(i + 1).copied().unwrap_or(0);
+            for (j, byte) in bytes.iter().enumerate() {
+                self.set_byte(offsets[i] + j, *byte);
+            }
+            self.set_byte(offsets[i] + NEXT_POINTER_OFFSET, next as u8);
+            self.set_byte_writable(offsets[i], false);
+            self.set_byte_writable(offsets[i] + NEXT_POINTER_OFFSET, false);
+        }
+        let head = offsets.first().copied().unwrap_or(0);
+        self.set_byte(device::CAPABILITIES_POINTER_OFFSET, head as u8);
+        self.set_capabilities_list(!offsets.is_empty());
+        Ok(offsets)
+    }
+
+    /// Installs `caps` as the extended capability list, in order, and returns
+    /// the offset of each capability.
+    ///
+    /// The capabilities are laid out as by `install_capabilities`, from the
+    /// start of the extended configuration space, and replace any previous
+    /// extended capability list. The header of each capability is read-only.
+    ///
+    /// Fails with `CapabilitiesOverflow` if the capabilities don't fit in the
+    /// implemented registers, leaving the configuration space untouched.
+    pub fn install_extended_capabilities(
+        &mut self,
+        caps: &[&dyn PciExtendedCapability],
+    ) -> Result<Vec<usize>> {
+        let images: Vec<Vec<u8>> = caps
+            .iter()
+            .map(|cap| {
+                let mut bytes = cap.to_bytes();
+                if bytes.len() < 4 {
+                    bytes.resize(4, 0);
+                }
+                bytes
+            })
+            .collect();
+
+        let area = EXTENDED_CAPABILITIES_START_OFFSET..self.registers.len() * 4;
+        let offsets = self.lay_out_capabilities(area.clone(), &images)?;
+        self.clear_capability_area(area);
+        for (i, (cap, bytes)) in caps.iter().zip(images.iter()).enumerate() {
+            for (j, byte) in bytes.iter().enumerate().skip(4) {
+                self.set_byte(offsets[i] + j, *byte);
+            }
+            let next = offsets.get(i + 1).copied().unwrap_or(0);
+            self.registers[offsets[i] / 4] =
+                extended_capability::header(cap.id(), cap.version(), next);
+            self.write_masks[offsets[i] / 4] = 0;
+        }
+        Ok(offsets)
+    }
+
+    // Returns the offsets of capabilities with the byte images `images`, laid
+    // out on dword boundaries in `area` around the device-specific region.
+    fn lay_out_capabilities(&self, area: Range<usize>, images: &[Vec<u8>]) -> Result<Vec<usize>> {
+        let mut offsets = Vec::with_capacity(images.len());
+        let mut offset = area.start;
+        let mut end = offset;
+        for bytes in images.iter() {
+            let region = &self.device_specific;
+            if offset < region.end && region.start < offset + bytes.len() {
+                offset = (region.end + 3) & !0x3;
+            }
+            offsets.push(offset);
+            end = offset + bytes.len();
+            offset = (end + 3) & !0x3;
+        }
+        if end > area.end {
+            return Err(Error::CapabilitiesOverflow(end - area.end));
+        }
+        Ok(offsets)
+    }
+
+    // Clears the registers of `area`, except the device-specific region, and
+    // makes them writable.
+    fn clear_capability_area(&mut self, area: Range<usize>) {
+        for offset in area {
+            if !self.device_specific.contains(&offset) {
+                self.set_byte(offset, 0);
+                self.set_byte_writable(offset, true);
+            }
+        }
+    }
+
+    /// Completes the setup of a type 0 header, once its BARs and
+    /// capabilities are added.
+    ///
+    /// This sets the Capabilities List bit of the Status register if the
+    /// Capabilities Pointer is set, and clears it otherwise, makes the Header
+    /// Type register describe a type 0 header, keeping its multi-function
+    /// bit, and disables the decoding of the BARs: the guest enables it once
+    /// it has placed them. The configuration is then checked, and every
+    /// problem found is returned.
+    pub fn finalize(&mut self) -> std::result::Result<(), Vec<SetupError>> {
+        let head = self.registers[device::CAPABILITIES_POINTER_OFFSET / 4]
+            >> ((device::CAPABILITIES_POINTER_OFFSET % 4) * 8);
+        self.set_capabilities_list(head & 0xff != 0);
+        let header = self.registers[HEADER_TYPE_OFFSET / 4] >> ((HEADER_TYPE_OFFSET % 4) * 8);
+        let multifunction = header as u8 & HEADER_TYPE_MULTIFUNCTION;
+        self.set_byte(
+            HEADER_TYPE_OFFSET,
+            u8::from(PciHeaderType::Device) | multifunction,
+        );
+        self.registers[COMMAND_OFFSET / 4] &=
+            !(u32::from(COMMAND_IO_SPACE | COMMAND_MEMORY_SPACE) << ((COMMAND_OFFSET % 4) * 8));
+        self.hardwire_absent_bars();
+
+        let mut problems = Vec::new();
+        let bars = self.bars();
+        for (i, bar) in bars.iter().enumerate() {
+            for other in bars[i + 1..].iter() {
+                if bar.region.intersects(&other.region) {
+                    problems.push(SetupError::BarOverlap(bar.index, other.index));
+                }
+            }
+        }
+        if let Err(e) = self.capabilities() {
+            problems.push(SetupError::CapabilityList(e));
+        }
+        if problems.is_empty() {
+            Ok(())
+        } else {
+            Err(problems)
+        }
+    }
+
+    // Sets or clears the Capabilities List bit of the Status register.
+    fn set_capabilities_list(&mut self, present: bool) {
+        let status_reg = &mut self.registers[STATUS_OFFSET / 4];
+        let status_bit = u32::from(STATUS_CAPABILITIES_LIST) << ((STATUS_OFFSET % 4) * 8);
+        if present {
+            *status_reg |= status_bit;
+        } else {
+            *status_reg &= !status_bit;
+        }
+    }
+
+    // Sets up the BAR described by `config` in the first `num_bars` slots.
+    fn add_bar_within(&mut self, config: PciBarConfig, num_bars: usize) -> bar::Result<()> {
+        let idx = config.index.get();
+        let (value, value_high) = bar::encode_registers(&config);
+        let (mask, mask_high) = (config.write_mask(), config.write_mask_high());
+        if idx >= num_bars {
+            return Err(bar::Error::BarIndex(idx));
+        }
+        if value_high.is_some() && idx + 1 >= num_bars {
+            return Err(bar::Error::BarInvalid64(idx));
+        }
+        let is_64bit = |slot: &Option<PciBarConfig>| {
+            slot.map_or(false, |config| config.write_mask_high().is_some())
+        };
+        if idx > 0 && is_64bit(&self.bars[idx - 1]) {
+            return Err(bar::Error::BarInUse64(idx));
+        }
+        if value_high.is_some() && self.bars[idx + 1].is_some() {
+            return Err(bar::Error::BarInUse64(idx + 1));
+        }
+
+        let reg_idx = device::BARS_START_OFFSET / 4 + idx;
+        if is_64bit(&self.bars[idx]) {
+            // The upper register of the replaced BAR goes back to an empty
+            // slot.
+            self.registers[reg_idx + 1] = 0;
+            self.write_masks[reg_idx + 1] = 0;
+        }
+        self.registers[reg_idx] = value;
+        self.write_masks[reg_idx] = mask;
+        if let (Some(value), Some(mask)) = (value_high, mask_high) {
+            self.registers[reg_idx + 1] = value;
+            self.write_masks[reg_idx + 1] = mask;
+        }
+        self.bars[idx] = Some(config);
+        Ok(())
+    }
+
+    // Makes the BAR slots of a type 0 header that hold no BAR read-only and
+    // read as 0, so that the guest skips them when sizing. The upper registers
+    // of 64-bit BARs are left alone.
+    fn hardwire_absent_bars(&mut self) {
+        let mut used = [false; device::NUM_BARS];
+        for config in self.bars() {
+            let idx = config.index.get();
+            used[idx] = true;
+            if config.write_mask_high().is_some() {
+                used[idx + 1] = true;
+            }
+        }
+        for ((value, mask), used) in self.bar_registers_mut(0..device::NUM_BARS).zip(used.iter()) {
+            if !used {
+                *value = 0;
+                *mask = 0;
+            }
+        }
+    }
+
+    // Returns the values and write masks of the BAR registers in `slots`.
+    fn bar_registers_mut(
+        &mut self,
+        slots: Range<usize>,
+    ) -> impl Iterator<Item = (&mut u32, &mut u32)> {
+        let first = device::BARS_START_OFFSET / 4;
+        let regs = first + slots.start..first + slots.end;
+        self.registers[regs.clone()]
+            .iter_mut()
+            .zip(self.write_masks[regs].iter_mut())
+    }
+
+    // Returns the byte at `offset`, regardless of the access modes.
+    fn byte(&self, offset: usize) -> u8 {
+        (self.registers[offset / 4] >> ((offset % 4) * 8)) as u8
+    }
+
+    // Stores the byte at `offset`, regardless of the bits the guest can write.
+    fn set_byte(&mut self, offset: usize, value: u8) {
+        let shift = (offset % 4) * 8;
+        let reg = &mut self.registers[offset / 4];
+        *reg = (*reg & !(0xff << shift)) | (u32::from(value) << shift);
+    }
+
+    fn set_byte_writable(&mut self, offset: usize, writable: bool) {
+        let bits = 0xff << ((offset % 4) * 8);
+        if writable {
+            self.write_masks[offset / 4] |= bits;
+        } else {
+            self.write_masks[offset / 4] &= !bits;
+        }
+    }
+
+    /// Declares the `len` bytes at `base` as the registers specific to the
+    /// device, accessed with `device_specific_read` and
+    /// `device_specific_write` at offsets relative to `base`.
+    ///
+    /// The region must lie after the standard header, and the capabilities
+    /// must be laid out around it. Fails with `OffsetOutOfBounds` if it
+    /// starts before `CAPABILITIES_START_OFFSET` or extends past the
+    /// implemented registers. The zero-filled registers don't count.
+    pub fn set_device_specific_region(&mut self, base: usize, len: usize) -> Result<()> {
+        if base < CAPABILITIES_START_OFFSET {
+            return Err(Error::OffsetOutOfBounds(base));
+        }
+        match base.checked_add(len) {
+            Some(end) if end <= self.registers.len() * 4 => {
+                self.device_specific = base..end;
+                Ok(())
+            }
+            _ => Err(Error::OffsetOutOfBounds(base)),
+        }
+    }
+
+    /// Returns the `len` bytes at `offset` in the device-specific region.
+    ///
+    /// Fails with `OffsetOutOfBounds` if the bytes extend past the end of the
+    /// region, which is empty until `set_device_specific_region` is called.
+    pub fn device_specific_read(&self, offset: usize, len: usize) -> Result<Vec<u8>> {
+        let start = self.device_specific_offset(offset, len)?;
+        Ok((start..start + len).map(|o| self.byte(o)).collect())
+    }
+
+    /// Stores `data` at `offset` in the device-specific region, regardless of
+    /// the bits the guest can write.
+    ///
+    /// Fails with `OffsetOutOfBounds` if the bytes extend past the end of the
+    /// region.
+    pub fn device_specific_write(&mut self, offset: usize, data: &[u8]) -> Result<()> {
+        let start = self.device_specific_offset(offset, data.len())?;
+        for (i, &value) in data.iter().enumerate() {
+            self.set_byte(start + i, value);
+        }
+        Ok(())
+    }
+
+    // Returns the offset in the configuration space of the `len` bytes at
+    // `offset` in the device-specific region.
+    fn device_specific_offset(&self, offset: usize, len: usize) -> Result<usize> {
+        let region = &self.device_specific;
+        match offset.checked_add(len) {
+            Some(end) if end <= region.len() && region.start + end <= self.registers.len() * 4 => {
+                Ok(region.start + offset)
+            }
+            _ => Err(Error::OffsetOutOfBounds(
+                region.start.saturating_add(offset),
+            )),
+        }
+    }
+
+    /// Returns the registers, for bulk operations such as saving the state.
+    ///
+    /// These are the stored values: the Interrupt Status bit, driven by
+    /// [`set_intx_asserted`](#method.set_intx_asserted), isn't part of them.
+    pub fn as_registers(&self) -> &[u32] {
+        &self.registers
+    }
+
+    /// Returns the registers for modification, for bulk operations such as
+    /// restoring the state.
+    ///
+    /// Changes made this way bypass the bits the guest can write and the
+    /// other guest view rules, like `apply_writes`.
+    pub fn as_registers_mut(&mut self) -> &mut [u32] {
+        &mut self.registers
+    }
+
+    /// Applies a sequence of `(register index, value)` writes.
+    ///
+    /// All the indices are checked before any write is done, so either every
+    /// write is applied or the configuration space is left untouched. The
+    /// values are stored as is, regardless of the bits the guest can write.
+    pub fn apply_writes(&mut self, writes: &[(usize, u32)]) -> Result<()> {
+        if let Some((reg_idx, _)) = writes
+            .iter()
+            .find(|(reg_idx, _)| *reg_idx >= self.registers.len())
+        {
+            return Err(Error::OffsetOutOfBounds(reg_idx.saturating_mul(4)));
+        }
+        for (reg_idx, value) in writes {
+            self.registers[*reg_idx] = *value;
+        }
+        Ok(())
+    }
+}
+
+/// Creates a zeroed configuration space for a function with the header type
+/// `header_type`.
+///
+/// A PCI Express configuration space is 4 KiB large and has the PCI Express
+/// mode enabled, a PCI one is 256 bytes large. This suits generic code that
+/// only learns the kind of function at runtime, for instance from a snapshot.
+/// The BAR registers are left writable, as the BARs can't be set up through
+/// the returned trait object, so that restoring a snapshot brings them back.
+pub fn new_config_for(header_type: PciHeaderType, pcie: bool) -> Box<dyn PciConfig> {
+    let mut config = if pcie {
+        ConfigSpace::new_pcie()
+    } else {
+        ConfigSpace::new()
+    };
+    config.set_pcie_mode(pcie);
+    config.set_byte(HEADER_TYPE_OFFSET, u8::from(header_type));
+    Box::new(config)
+}
+
+impl Default for ConfigSpace {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl PciConfig for ConfigSpace {
+    fn read_register(&self, reg_idx: usize) -> Result<u32> {
+        match self.registers.get(reg_idx) {
+            Some(value) if reg_idx == STATUS_REG => Ok(if self.intx_asserted {
+                value | STATUS_INTERRUPT_BIT
+            } else {
+                value & !STATUS_INTERRUPT_BIT
+            }),
+            Some(value) => Ok(*value),
+            None if reg_idx < self.size() / 4 => Ok(0),
+            None => Err(Error::OffsetOutOfBounds(reg_idx.saturating_mul(4))),
+        }
+    }
+
+    fn size(&self) -> usize {
+        if self.zero_fill {
+            PCIE_CONFIG_SPACE_SIZE.max(self.registers.len() * 4)
+        } else {
+            self.registers.len() * 4
+        }
+    }
+
+    fn write_register(&mut self, reg_idx: usize, mut value: u32) -> Result<()> {
+        // The stored value, without the Interrupt Status bit read_register
+        // overrides.
+        let old = match self.registers.get(reg_idx) {
+            Some(old) => *old,
+            None if reg_idx < self.size() / 4 => return Ok(()),
+            None => return Err(Error::OffsetOutOfBounds(reg_idx.saturating_mul(4))),
+        };
+        if self.pcie_mode && reg_idx == CACHE_LINE_LATENCY_REG {
+            value = (value & 0xffff_0000) | (old & 0xff);
+        }
+        if let Some(value) = self.fixed_bar_write(reg_idx, value) {
+            self.registers[reg_idx] = value;
+            return Ok(());
+        }
+        let mask = self.write_masks[reg_idx];
+        let mut new = (old & !mask) | (value & mask);
+        if reg_idx == STATUS_REG {
+            // The error bits are cleared by writing 1 and kept by writing 0.
+            new = (new & !STATUS_WRITE_1_TO_CLEAR_BITS)
+                | (old & STATUS_WRITE_1_TO_CLEAR_BITS & !value);
+        }
+        self.registers[reg_idx] = new;
+        Ok(())
+    }
+
+    // The Capabilities List bit is read-only to the guest, so it is set by the
+    // device.
+    fn set_capabilities_list_bit(&mut self) -> Result<()> {
+        self.set_capabilities_list(true);
+        Ok(())
+    }
+
+    fn clear_capabilities_list_bit(&mut self) -> Result<()> {
+        self.set_capabilities_list(false);
+        Ok(())
+    }
+
+    // The reset is done by the device: it reaches the bits the guest can't
+    // write, and deasserts INTx.
+    fn apply_reset(&mut self, kind: ResetKind, policy: &ResetPolicy) -> Result<()> {
+        reset::reset_registers(&mut self.registers, kind, policy);
+        self.intx_asserted = false;
+        Ok(())
+    }
+}
+
+impl PciDeviceConfig for ConfigSpace {
+    fn add_bar(&mut self, config: PciBarConfig) -> bar::Result<()> {
+        self.add_bar_within(config, self.num_bars)
+    }
+
+    fn num_bars(&self) -> usize {
+        self.num_bars
+    }
+
+    fn bars(&self) -> Vec<PciBarConfig> {
+        self.bars.iter().flatten().copied().collect()
+    }
+}
+
+impl PciBridgeConfig for ConfigSpace {
+    fn add_bar(&mut self, config: PciBarConfig) -> bar::Result<()> {
+        self.add_bar_within(config, bridge::NUM_BARS.min(self.num_bars))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bar::{PciBarPrefetchable, PciBarRegion};
+    use crate::msi::MsiCap;
+    use crate::pci_config::{Command, Status};
+    use crate::power_management::PowerManagementCap;
+
+    fn mem_bar(idx: usize, addr: u32, len: u32) -> PciBarConfig {
+        PciBarConfig {
+            index: BarIndex::new(idx).unwrap(),
+            region: PciBarRegion::new_32bit_mem_region(addr, len).unwrap(),
+            prefetchable: PciBarPrefetchable::NotPrefetchable,
+            fixed: false,
+        }
+    }
+
+    #[test]
+    fn test_unimplemented_bar_reads_zero() {
+        let mut config = ConfigSpace::minimal(0x1234, 0x5678, 0x02, 0x00);
+        PciDeviceConfig::add_bar(&mut config, mem_bar(0, 0x1000_0000, 0x1000)).unwrap();
+
+        let bar1 = device::BARS_START_OFFSET / 4 + 1;
+        config.write_register(bar1, u32::MAX).unwrap();
+        assert_eq!(config.read_register(bar1).unwrap(), 0);
+        assert!(config.is_bar_implemented(BarIndex::new(0).unwrap()));
+        assert!(!config.is_bar_implemented(BarIndex::new(1).unwrap()));
+    }
+
+    #[test]
+    fn test_bridge_bus_numbers_writable() {
+        let mut config = ConfigSpace::new();
+        config.set_byte(HEADER_TYPE_OFFSET, u8::from(PciHeaderType::PciToPciBridge));
+
+        config
+            .write_register(bridge::PRIMARY_BUS_OFFSET / 4, 0x0005_0100)
+            .unwrap();
+        assert_eq!(config.bus_range().unwrap(), 1..=5);
+
+        let mut config = new_config_for(PciHeaderType::PciToPciBridge, false);
+        config
+            .write_register(bridge::PRIMARY_BUS_OFFSET / 4, 0x0003_0200)
+            .unwrap();
+        assert_eq!(config.read_byte(bridge::SECONDARY_BUS_OFFSET).unwrap(), 2);
+        assert_eq!(config.read_byte(bridge::SUBORDINATE_BUS_OFFSET).unwrap(), 3);
+    }
+
+    #[test]
+    fn test_install_capabilities_replaces_list() {
+        let mut config = ConfigSpace::minimal(0x1234, 0x5678, 0x02, 0x00);
+        let pm = PowerManagementCap::default();
+        let msi = MsiCap::default();
+        assert_eq!(
+            config.install_capabilities(&[&pm, &msi]).unwrap(),
+            vec![0x40, 0x48]
+        );
+        config.set_capability_writable(0x40, 8, &[]).unwrap();
+
+        assert_eq!(config.install_capabilities(&[&pm]).unwrap(), vec![0x40]);
+        assert_eq!(config.capabilities().unwrap().len(), 1);
+        assert_eq!(config.read_register(0x48 / 4).unwrap(), 0);
+        config.write_register(0x44 / 4, 0x0000_0003).unwrap();
+        assert_eq!(config.read_register(0x44 / 4).unwrap(), 0x0000_0003);
+    }
+
+    #[test]
+    fn test_install_capabilities_around_device_specific() {
+        let mut config = ConfigSpace::minimal(0x1234, 0x5678, 0x02, 0x00);
+        config.set_device_specific_region(0x44, 0xC).unwrap();
+        config.device_specific_write(0, &[1, 2, 3, 4]).unwrap();
+        let pm = PowerManagementCap::default();
+        let msi = MsiCap::default();
+
+        assert_eq!(
+            config.install_capabilities(&[&pm, &msi]).unwrap(),
+            vec![0x50, 0x58]
+        );
+        assert_eq!(config.device_specific_read(0, 4).unwrap(), vec![1, 2, 3, 4]);
+        assert_eq!(config.capabilities().unwrap().len(), 2);
+    }
+
+    #[test]
+    fn test_install_capabilities_headers_read_only() {
+        let mut config = ConfigSpace::minimal(0x1234, 0x5678, 0x02, 0x00);
+        let pm = PowerManagementCap::default();
+        let msi = MsiCap::default();
+        config.install_capabilities(&[&pm, &msi]).unwrap();
+
+        config.write_word(0x40, 0xffff).unwrap();
+        config.write_word(0x48, 0xffff).unwrap();
+        assert_eq!(config.read_word(0x40).unwrap(), 0x4801);
+        assert_eq!(config.read_word(0x48).unwrap(), 0x0005);
+        assert_eq!(config.capabilities().unwrap().len(), 2);
+    }
+
+    #[test]
+    fn test_status_write_1_to_clear() {
+        let mut config = ConfigSpace::minimal(0x1234, 0x5678, 0x02, 0x00);
+        config.as_registers_mut()[STATUS_REG] = 0xC000_0000;
+
+        config
+            .write_word(COMMAND_OFFSET, COMMAND_MEMORY_SPACE)
+            .unwrap();
+        let (command, status) = config.command_status().unwrap();
+        assert!(command.memory_space() && !command.io_space());
+        assert_eq!(status, Status(0xC000));
+        config.write_word(STATUS_OFFSET, 0x0000).unwrap();
+        assert_eq!(config.read_word(STATUS_OFFSET).unwrap(), 0xC000);
+
+        config
+            .write_command_status(Command(COMMAND_IO_SPACE), Status(0x4010))
+            .unwrap();
+        assert_eq!(
+            config.command_status().unwrap(),
+            (Command(0x0001), Status(0x8000))
+        );
+        let (command, status) = config.command_status().unwrap();
+        config.write_command_status(command, status).unwrap();
+        assert_eq!(
+            config.command_status().unwrap(),
+            (Command(0x0001), Status(0))
+        );
+        config.write_register(STATUS_REG, 0xFFFF_0000).unwrap();
+        assert_eq!(config.command_status().unwrap(), (Command(0), Status(0)));
+    }
+
+    #[test]
+    fn test_interrupt_status_not_stored() {
+        let mut config = ConfigSpace::minimal(0x1234, 0x5678, 0x02, 0x00);
+        config.set_intx_asserted(true);
+        config
+            .write_word(COMMAND_OFFSET, COMMAND_MEMORY_SPACE)
+            .unwrap();
+        assert_eq!(config.as_registers()[STATUS_REG] & STATUS_INTERRUPT_BIT, 0);
+
+        config.set_intx_asserted(false);
+        assert_eq!(
+            config.read_word(STATUS_OFFSET).unwrap() & STATUS_INTERRUPT,
+            0
+        );
+    }
+
+    #[test]
+    fn test_device_specific_region_bounds() {
+        let mut config = ConfigSpace::new();
+        config.set_zero_fill(true);
+        assert_eq!(
+            config.set_device_specific_region(0x100, 0x10),
+            Err(Error::OffsetOutOfBounds(0x100))
+        );
+        assert_eq!(
+            config.set_device_specific_region(0xF0, 0x20),
+            Err(Error::OffsetOutOfBounds(0xF0))
+        );
+
+        config.set_device_specific_region(0xF0, 0x10).unwrap();
+        config
+            .device_specific_write(0xC, &[0xAB, 0xCD, 0xEF, 0x01])
+            .unwrap();
+        assert_eq!(
+            config.device_specific_read(0xC, 4).unwrap(),
+            vec![0xAB, 0xCD, 0xEF, 0x01]
+        );
+        assert_eq!(
+            config.device_specific_read(0xD, 4),
+            Err(Error::OffsetOutOfBounds(0xFD))
+        );
+    }
+
+    #[test]
+    fn test_fixed_bar_sizing() {
+        let mut config = ConfigSpace::minimal(0x1234, 0x5678, 0x02, 0x00);
+        let bar = PciBarConfig {
+            fixed: true,
+            ..mem_bar(0, 0xE000_0000, 0x1000)
+        };
+        PciDeviceConfig::add_bar(&mut config, bar).unwrap();
+        let bar0 = device::BARS_START_OFFSET / 4;
+
+        // Without sizing, the probe reads back the fixed address.
+        config.write_register(bar0, u32::MAX).unwrap();
+        assert_eq!(config.read_register(bar0).unwrap(), 0xE000_0000);
+
+        config.set_fixed_bar_sizing(true);
+        // Probe, then restore: the size mask, then the fixed address.
+        config.write_register(bar0, u32::MAX).unwrap();
+        assert_eq!(config.read_register(bar0).unwrap(), 0xFFFF_F000);
+        config.write_register(bar0, 0xE000_0000).unwrap();
+        assert_eq!(config.read_register(bar0).unwrap(), 0xE000_0000);
+
+        // Relocation: ignored.
+        config.write_register(bar0, 0xD000_0000).unwrap();
+        assert_eq!(config.read_register(bar0).unwrap(), 0xE000_0000);
+        config.write_register(bar0, u32::MAX).unwrap();
+        config.write_register(bar0, 0xD000_0000).unwrap();
+        assert_eq!(config.read_register(bar0).unwrap(), 0xE000_0000);
+    }
+
+    #[test]
+    fn test_64bit_bar_pairing() {
+        let mut config = ConfigSpace::minimal(0x1234, 0x5678, 0x02, 0x00);
+        let bar64 = |idx| PciBarConfig {
+            index: BarIndex::new(idx).unwrap(),
+            region: PciBarRegion::new_64bit_mem_region(0x1_0000_0000, 0x1000).unwrap(),
+            prefetchable: PciBarPrefetchable::NotPrefetchable,
+            fixed: false,
+        };
+
+        assert_eq!(
+            PciDeviceConfig::add_bar(&mut config, bar64(5)),
+            Err(bar::Error::BarInvalid64(5))
+        );
+        PciDeviceConfig::add_bar(&mut config, bar64(4)).unwrap();
+        assert_eq!(
+            PciDeviceConfig::add_bar(&mut config, mem_bar(5, 0x1000_0000, 0x1000)),
+            Err(bar::Error::BarInUse64(5))
+        );
+        assert!(config.is_bar_implemented(BarIndex::new(5).unwrap()));
+    }
+
+    #[test]
+    fn test_device_without_bars() {
+        for num_bars in [device::NUM_BARS, 0].iter() {
+            let mut config = ConfigSpace::minimal(0x1234, 0x5678, 0xff, 0x00);
+            config.set_num_bars(*num_bars).unwrap();
+            assert_eq!(config.finalize(), Ok(()));
+            assert!(config.bars().is_empty());
+            assert_eq!(config.total_memory_footprint(), 0);
+            assert_eq!(config.total_io_footprint(), 0);
+            for idx in 0..device::NUM_BARS {
+                let reg_idx = device::BARS_START_OFFSET / 4 + idx;
+                config.write_register(reg_idx, u32::MAX).unwrap();
+                assert_eq!(config.read_register(reg_idx).unwrap(), 0);
+            }
+        }
+    }
+
+    #[test]
+    fn test_new_config_for_bars_writable() {
+        let mut config = new_config_for(PciHeaderType::Device, false);
+        let bar0 = device::BARS_START_OFFSET / 4;
+        config.write_register(bar0, 0xfebf_0000).unwrap();
+        assert_eq!(config.read_register(bar0).unwrap(), 0xfebf_0000);
+        config.write_register(bar0 + 5, 0x0000_c001).unwrap();
+        assert_eq!(config.read_register(bar0 + 5).unwrap(), 0x0000_c001);
+    }
+
+    #[test]
+    fn test_capabilities_list_bit() {
+        let mut config = ConfigSpace::minimal(0x1234, 0x5678, 0x02, 0x00);
+        config.set_capabilities_list_bit().unwrap();
+        assert_eq!(
+            config.read_word(STATUS_OFFSET).unwrap(),
+            STATUS_CAPABILITIES_LIST
+        );
+        config.clear_capabilities_list_bit().unwrap();
+        assert_eq!(config.read_word(STATUS_OFFSET).unwrap(), 0);
+    }
+}
@@ -0,0 +1,358 @@
+//! Type 0 (endpoint device) configuration header.
+
+use std::convert::TryFrom;
+
+use crate::bar::{self, BarIndex, BarState, PciBarConfig, PciBarRegion};
+use crate::capability;
+use crate::layout;
+use crate::pci_config::{PciConfig, PciHeaderType, Result, BIST_OFFSET};
+
+/// Offset of the first Base Address Register.
+pub const BARS_START_OFFSET: usize = 0x10;
+/// Number of Base Address Registers in a type 0 header.
+pub const NUM_BARS: usize = 6;
+/// Offset of the CardBus CIS pointer.
+pub const CARDBUS_CIS_OFFSET: usize = 0x28;
+/// Offset of the Subsystem Vendor ID.
+pub const SUBSYSTEM_VENDOR_ID_OFFSET: usize = 0x2C;
+/// Offset of the Subsystem ID.
+pub const SUBSYSTEM_ID_OFFSET: usize = 0x2E;
+/// Offset of the Expansion ROM Base Address register.
+pub const ROM_BAR_OFFSET: usize = 0x30;
+/// Offset of the Capabilities Pointer.
+pub const CAPABILITIES_POINTER_OFFSET: usize = 0x34;
+/// Offset of the Interrupt Line.
+pub const INTERRUPT_LINE_OFFSET: usize = 0x3C;
+/// Offset of the Interrupt Pin.
+pub const INTERRUPT_PIN_OFFSET: usize = 0x3D;
+/// Offset of the Min_Gnt register.
+pub const MIN_GRANT_OFFSET: usize = 0x3E;
+/// Offset of the Max_Lat register.
+pub const MAX_LATENCY_OFFSET: usize = 0x3F;
+
+// The header-specific fields follow the common header, in order, without
+// overlapping.
+const _: () = assert!(BARS_START_OFFSET == BIST_OFFSET + 1);
+const _: () = assert!(BARS_START_OFFSET + NUM_BARS * 4 <= CARDBUS_CIS_OFFSET);
+const _: () = assert!(CARDBUS_CIS_OFFSET + 4 <= SUBSYSTEM_VENDOR_ID_OFFSET);
+const _: () = assert!(SUBSYSTEM_ID_OFFSET + 2 <= ROM_BAR_OFFSET);
+const _: () = assert!(ROM_BAR_OFFSET + 4 <= CAPABILITIES_POINTER_OFFSET);
+const _: () = assert!(MAX_LATENCY_OFFSET < capability::CAPABILITIES_START_OFFSET);
+
+/// Accessors for the fields of a type 0 configuration header.
+pub trait PciDeviceConfig: PciConfig {
+    /// Returns the Min_Gnt register, the burst period the device needs in
+    /// units of 0.25us.
+    fn min_grant(&self) -> Result<u8> {
+        self.read_byte(MIN_GRANT_OFFSET)
+    }
+
+    /// Sets the Min_Gnt register.
+    fn write_min_grant(&mut self, value: u8) -> Result<()> {
+        self.write_byte(MIN_GRANT_OFFSET, value)
+    }
+
+    /// Returns the Max_Lat register, how often the device needs bus access
+    /// in units of 0.25us.
+    fn max_latency(&self) -> Result<u8> {
+        self.read_byte(MAX_LATENCY_OFFSET)
+    }
+
+    /// Sets the Max_Lat register.
+    fn write_max_latency(&mut self, value: u8) -> Result<()> {
+        self.write_byte(MAX_LATENCY_OFFSET, value)
+    }
+
+    /// Sets up the BAR, or pair of BARs, described by `config`.
+    ///
+    /// Implementations write the BAR registers and arrange for guest writes to
+    /// only change the address bits the region size allows, none for a fixed
+    /// BAR. A 64-bit BAR takes two slots: it fails with `BarInvalid64` in the
+    /// last slot, and with `BarInUse64` if either slot would also hold
+    /// another BAR. A BAR already set up at the same index is replaced.
+    fn add_bar(&mut self, config: PciBarConfig) -> bar::Result<()>;
+
+    /// Returns the number of BARs the device implements, starting from BAR0.
+    ///
+    /// Defaults to `NUM_BARS`. Devices exposing fewer BARs override this; 0 is
+    /// valid for devices without BARs.
+    fn num_bars(&self) -> usize {
+        NUM_BARS
+    }
+
+    /// Returns the configuration of the BARs set up with `add_bar`, by
+    /// increasing index.
+    fn bars(&self) -> Vec<PciBarConfig>;
+
+    /// Returns whether the BAR slot at `idx` is implemented: it holds a BAR
+    /// set up with `add_bar`, or the upper register of a 64-bit one.
+    ///
+    /// Other slots are hardwired to 0, so that a guest writing all ones to
+    /// size them reads back 0 and skips them.
+    fn is_bar_implemented(&self, idx: BarIndex) -> bool {
+        idx.get() < self.num_bars()
+            && self.bars().iter().any(|config| {
+                config.index == idx
+                    || (config.write_mask_high().is_some() && config.index.get() + 1 == idx.get())
+            })
+    }
+
+    /// Returns the BAR topology of the device, to be restored with
+    /// [`restore_bars`](#method.restore_bars).
+    fn save_bars(&self) -> BarState {
+        BarState {
+            bars: self.bars(),
+            rom_size: self.rom_size(),
+        }
+    }
+
+    /// Sets up the BARs of `state` again, typically after restoring the
+    /// registers of the device.
+    ///
+    /// Each BAR is added with `add_bar`, then the address held in its
+    /// registers before the call is written back as a guest write would be:
+    /// the encoding bits come from `state`, and only the address bits the BAR
+    /// decodes are kept, whatever the register image held. Fails with
+    /// `RomSizeMismatch` if the expansion ROM of the device doesn't have the
+    /// saved size.
+    fn restore_bars(&mut self, state: &BarState) -> bar::Result<()> {
+        if state.rom_size != self.rom_size() {
+            return Err(bar::Error::RomSizeMismatch(state.rom_size));
+        }
+        for config in state.bars.iter() {
+            let (reg_idx, values) = bar::encode_bar(config);
+            let addresses = (reg_idx..reg_idx + values.len())
+                .map(|reg_idx| self.read_register(reg_idx))
+                .collect::<Result<Vec<u32>>>()?;
+            self.add_bar(*config)?;
+            for (i, address) in addresses.into_iter().enumerate() {
+                self.write_register(reg_idx + i, address)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Returns the size of the expansion ROM in bytes, or 0 if the device has
+    /// none.
+    ///
+    /// Devices with an expansion ROM override this, the default being 0.
+    fn rom_size(&self) -> u32 {
+        0
+    }
+
+    /// Returns the memory space decoded by the device: the sum of the
+    /// decoded sizes of its memory BARs and of its expansion ROM.
+    ///
+    /// BARs and ROMs decode a power-of-two size at least as large as their
+    /// region, which is what needs to be allocated in the guest address
+    /// space.
+    fn total_memory_footprint(&self) -> u64 {
+        let bars = self
+            .bars()
+            .iter()
+            .filter(|config| !matches!(config.region, PciBarRegion::Io { .. }))
+            .fold(0u64, |total, config| {
+                total.saturating_add(PciBarRegion::required_size(config.region.size(), false))
+            });
+        let rom = match self.rom_size() {
+            0 => 0,
+            size => u64::from(size)
+                .next_power_of_two()
+                .max(u64::from(!bar::ROM_BAR_ADDRESS_MASK) + 1),
+        };
+        bars.saturating_add(rom)
+    }
+
+    /// Returns the I/O space decoded by the device: the sum of the decoded
+    /// sizes of its I/O BARs.
+    fn total_io_footprint(&self) -> u64 {
+        self.bars()
+            .iter()
+            .filter(|config| matches!(config.region, PciBarRegion::Io { .. }))
+            .fold(0u64, |total, config| {
+                total.saturating_add(PciBarRegion::required_size(config.region.size(), true))
+            })
+    }
+
+    /// Returns the `(offset, length)` byte ranges of the configuration space
+    /// that hold meaningful registers, by increasing offset.
+    ///
+    /// These are the header fields, leaving out the BARs that aren't set up,
+    /// and the capabilities. Other bytes are reserved and read as 0.
+    fn occupied_ranges(&self) -> Result<Vec<(usize, usize)>> {
+        let mut ranges: Vec<(usize, usize)> = layout::fields(PciHeaderType::Device)
+            .filter(|(_, offset, _)| {
+                !(BARS_START_OFFSET..BARS_START_OFFSET + NUM_BARS * 4).contains(offset)
+            })
+            .map(|(_, offset, len)| (offset, len))
+            .collect();
+        for config in self.bars() {
+            let len = match config.region {
+                PciBarRegion::Memory64 { .. } => 8,
+                _ => 4,
+            };
+            ranges.push((BARS_START_OFFSET + config.index.get() * 4, len));
+        }
+        let regions = self.capabilities()?;
+        for region in regions.iter() {
+            ranges.push((region.offset, capability::length(self, region, &regions)?));
+        }
+        ranges.sort_unstable();
+        Ok(ranges)
+    }
+
+    /// Returns the base address programmed in the BAR at `idx`.
+    ///
+    /// For a 64-bit BAR, `idx` must be the index of the lower half of the
+    /// pair.
+    fn bar_address(&self, idx: BarIndex) -> bar::Result<u64> {
+        if idx.get() >= self.num_bars() {
+            return Err(bar::Error::BarIndex(idx.get()));
+        }
+        bar::read_bar_address(self, BARS_START_OFFSET, self.num_bars(), idx.get())
+    }
+
+    /// Writes `value` to the BAR register at `idx`, as the guest would.
+    ///
+    /// Only the address bits the BAR decodes change: the encoding bits and
+    /// the address bits below the size of the region keep the value the
+    /// device set, as do all the bits of a fixed BAR. `idx` may be the index
+    /// of the upper register of a 64-bit BAR. Writes to BARs that aren't set
+    /// up are ignored.
+    fn handle_bar_write(&mut self, idx: BarIndex, value: u32) -> bar::Result<()> {
+        if idx.get() >= self.num_bars() {
+            return Err(bar::Error::BarIndex(idx.get()));
+        }
+        let mask = self
+            .bars()
+            .iter()
+            .find_map(|config| match config.index.get() {
+                i if i == idx.get() => Some(config.write_mask()),
+                i if i + 1 == idx.get() => config.write_mask_high(),
+                _ => None,
+            })
+            .unwrap_or(0);
+        let reg_idx = BARS_START_OFFSET / 4 + idx.get();
+        let old = self.read_register(reg_idx)?;
+        self.write_register(reg_idx, (old & !mask) | (value & mask))?;
+        Ok(())
+    }
+
+    /// Moves the BAR at `idx`, set up with `add_bar`, to `new_base` and returns
+    /// the region it now decodes.
+    ///
+    /// `new_base` must be aligned to the size the BAR decodes and the region
+    /// must fit in the address space of the BAR, otherwise this fails with
+    /// `BarAddressInvalid`. Fixed BARs can't be relocated. Both registers of a
+    /// 64-bit BAR are updated, the region returned being the one to map in
+    /// place of the previous one.
+    fn relocate_bar(&mut self, idx: BarIndex, new_base: u64) -> bar::Result<PciBarRegion> {
+        let config = self
+            .bars()
+            .into_iter()
+            .find(|config| config.index == idx)
+            .ok_or(bar::Error::BarIndex(idx.get()))?;
+        if config.fixed {
+            return Err(bar::Error::BarFixed(idx.get()));
+        }
+        let len = config.region.size();
+        let is_io = matches!(config.region, PciBarRegion::Io { .. });
+        let size = PciBarRegion::required_size(len, is_io);
+        if size == 0 || new_base & (size - 1) != 0 {
+            return Err(bar::Error::BarAddressInvalid(new_base, len));
+        }
+        let base32 =
+            u32::try_from(new_base).map_err(|_| bar::Error::BarAddressInvalid(new_base, len));
+        let region = match config.region {
+            PciBarRegion::Io { len, .. } => PciBarRegion::new_io_region(base32?, len)?,
+            PciBarRegion::Memory32 { len, .. } => PciBarRegion::new_32bit_mem_region(base32?, len)?,
+            PciBarRegion::Memory64 { len, .. } => {
+                PciBarRegion::new_64bit_mem_region(new_base, len)?
+            }
+        };
+        let (reg_idx, values) = bar::encode_bar(&PciBarConfig { region, ..config });
+        for (i, value) in values.into_iter().enumerate() {
+            self.write_register(reg_idx + i, value)?;
+        }
+        Ok(region)
+    }
+
+    /// Returns the base address programmed in the Expansion ROM BAR.
+    fn rom_base_address(&self) -> Result<u32> {
+        bar::read_rom_base_address(self, ROM_BAR_OFFSET)
+    }
+
+    /// Programs the base address of the Expansion ROM BAR, leaving the enable
+    /// bit untouched.
+    fn write_rom_base_address(&mut self, addr: u32) -> Result<()> {
+        bar::write_rom_base_address(self, ROM_BAR_OFFSET, addr)
+    }
+
+    /// Returns whether the Expansion ROM BAR enable bit is set, in which case
+    /// the ROM is mapped at its base address.
+    fn rom_enabled(&self) -> Result<bool> {
+        bar::read_rom_enabled(self, ROM_BAR_OFFSET)
+    }
+
+    /// Sets or clears the Expansion ROM BAR enable bit, leaving the base
+    /// address untouched.
+    fn set_rom_enable(&mut self, enable: bool) -> Result<()> {
+        bar::write_rom_enable(self, ROM_BAR_OFFSET, enable)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bar::PciBarPrefetchable;
+    use crate::config_space::ConfigSpace;
+
+    fn device() -> ConfigSpace {
+        ConfigSpace::minimal(0x1234, 0x5678, 0x02, 0x00)
+    }
+
+    #[test]
+    fn test_handle_bar_write() {
+        let mut config = device();
+        config
+            .add_bar(PciBarConfig {
+                index: BarIndex::new(1).unwrap(),
+                region: PciBarRegion::new_32bit_mem_region(0xFEB0_0000, 0x1000).unwrap(),
+                prefetchable: PciBarPrefetchable::Prefetchable,
+                fixed: false,
+            })
+            .unwrap();
+        let idx = BarIndex::new(1).unwrap();
+
+        config.handle_bar_write(idx, 0xFFFF_FFFF).unwrap();
+        assert_eq!(
+            config.read_register(BARS_START_OFFSET / 4 + 1).unwrap(),
+            0xFFFF_F008
+        );
+        config.handle_bar_write(idx, 0xC000_0FFF).unwrap();
+        assert_eq!(config.bar_address(idx).unwrap(), 0xC000_0000);
+        assert_eq!(
+            config.read_register(BARS_START_OFFSET / 4 + 1).unwrap(),
+            0xC000_0008
+        );
+    }
+
+    #[test]
+    fn test_rom_enable() {
+        let mut config = device();
+        config.write_rom_base_address(0xFEB0_0000).unwrap();
+        assert!(!config.rom_enabled().unwrap());
+
+        config.set_rom_enable(true).unwrap();
+        assert!(config.rom_enabled().unwrap());
+        assert_eq!(config.rom_base_address().unwrap(), 0xFEB0_0000);
+        assert_eq!(
+            config.read_register(ROM_BAR_OFFSET / 4).unwrap(),
+            0xFEB0_0001
+        );
+
+        config.set_rom_enable(false).unwrap();
+        assert!(!config.rom_enabled().unwrap());
+        assert_eq!(config.rom_base_address().unwrap(), 0xFEB0_0000);
+    }
+}
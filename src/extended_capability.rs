@@ -0,0 +1,191 @@
+//! PCI Express extended capabilities.
+//!
+//! Extended capabilities live in the extended configuration space, starting
+//! at offset 0x100. Each one starts with a 32-bit header holding a 16-bit ID,
+//! a 4-bit version and the 12-bit offset of the next extended capability.
+
+use crate::pci_config::{Error, PciConfig, Result, PCIE_CONFIG_SPACE_SIZE};
+
+/// Offset of the first extended capability.
+pub const EXTENDED_CAPABILITIES_START_OFFSET: usize = 0x100;
+
+/// Shift of the version in the extended capability header.
+const VERSION_SHIFT: u32 = 16;
+/// Mask of the version, once shifted.
+const VERSION_MASK: u32 = 0xf;
+/// Shift of the next capability offset in the extended capability header.
+const NEXT_SHIFT: u32 = 20;
+/// Reserved low bits of the next capability offset.
+const NEXT_RESERVED_MASK: usize = 0x3;
+
+/// Extended capability IDs.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum PciExtendedCapabilityId {
+    /// Null capability.
+    Null,
+    /// Advanced Error Reporting.
+    AdvancedErrorReporting,
+    /// Virtual Channel.
+    VirtualChannel,
+    /// Device Serial Number.
+    DeviceSerialNumber,
+    /// Power Budgeting.
+    PowerBudgeting,
+    /// Access Control Services.
+    AccessControlServices,
+    /// Alternative Routing-ID Interpretation.
+    AlternativeRoutingId,
+    /// Address Translation Services.
+    AddressTranslationServices,
+    /// Single Root I/O Virtualization.
+    SingleRootIoVirtualization,
+    /// Page Request Interface.
+    PageRequest,
+    /// Resizable BAR.
+    ResizableBar,
+    /// Latency Tolerance Reporting.
+    LatencyToleranceReporting,
+    /// Process Address Space ID.
+    ProcessAddressSpaceId,
+    /// L1 PM Substates.
+    L1PmSubstates,
+    /// ID not known to the crate.
+    Unknown(u16),
+}
+
+impl PciExtendedCapabilityId {
+    /// Returns the highest version of the capability the crate knows, or
+    /// `None` for IDs the crate doesn't know.
+    pub fn max_known_version(self) -> Option<u8> {
+        match self {
+            PciExtendedCapabilityId::Unknown(_) => None,
+            PciExtendedCapabilityId::Null => Some(0),
+            PciExtendedCapabilityId::AdvancedErrorReporting => Some(2),
+            _ => Some(1),
+        }
+    }
+}
+
+impl From<u16> for PciExtendedCapabilityId {
+    fn from(id: u16) -> Self {
+        match id {
+            0x0000 => PciExtendedCapabilityId::Null,
+            0x0001 => PciExtendedCapabilityId::AdvancedErrorReporting,
+            0x0002 => PciExtendedCapabilityId::VirtualChannel,
+            0x0003 => PciExtendedCapabilityId::DeviceSerialNumber,
+            0x0004 => PciExtendedCapabilityId::PowerBudgeting,
+            0x000D => PciExtendedCapabilityId::AccessControlServices,
+            0x000E => PciExtendedCapabilityId::AlternativeRoutingId,
+            0x000F => PciExtendedCapabilityId::AddressTranslationServices,
+            0x0010 => PciExtendedCapabilityId::SingleRootIoVirtualization,
+            0x0013 => PciExtendedCapabilityId::PageRequest,
+            0x0015 => PciExtendedCapabilityId::ResizableBar,
+            0x0018 => PciExtendedCapabilityId::LatencyToleranceReporting,
+            0x001B => PciExtendedCapabilityId::ProcessAddressSpaceId,
+            0x001E => PciExtendedCapabilityId::L1PmSubstates,
+            id => PciExtendedCapabilityId::Unknown(id),
+        }
+    }
+}
+
+impl From<PciExtendedCapabilityId> for u16 {
+    fn from(id: PciExtendedCapabilityId) -> Self {
+        match id {
+            PciExtendedCapabilityId::Null => 0x0000,
+            PciExtendedCapabilityId::AdvancedErrorReporting => 0x0001,
+            PciExtendedCapabilityId::VirtualChannel => 0x0002,
+            PciExtendedCapabilityId::DeviceSerialNumber => 0x0003,
+            PciExtendedCapabilityId::PowerBudgeting => 0x0004,
+            PciExtendedCapabilityId::AccessControlServices => 0x000D,
+            PciExtendedCapabilityId::AlternativeRoutingId => 0x000E,
+            PciExtendedCapabilityId::AddressTranslationServices => 0x000F,
+            PciExtendedCapabilityId::SingleRootIoVirtualization => 0x0010,
+            PciExtendedCapabilityId::PageRequest => 0x0013,
+            PciExtendedCapabilityId::ResizableBar => 0x0015,
+            PciExtendedCapabilityId::LatencyToleranceReporting => 0x0018,
+            PciExtendedCapabilityId::ProcessAddressSpaceId => 0x001B,
+            PciExtendedCapabilityId::L1PmSubstates => 0x001E,
+            PciExtendedCapabilityId::Unknown(id) => id,
+        }
+    }
+}
+
+/// Extended capability that can be installed in a configuration space.
+pub trait PciExtendedCapability {
+    /// Returns the ID of the capability.
+    fn id(&self) -> PciExtendedCapabilityId;
+
+    /// Returns the version of the capability.
+    fn version(&self) -> u8;
+
+    /// Returns the byte image of the capability, starting at the header.
+    ///
+    /// The header is filled in when the capability is installed.
+    fn to_bytes(&self) -> Vec<u8>;
+}
+
+// Returns the header of an extended capability with the given ID and
+// version, followed by the capability at `next`.
+pub(crate) fn header(id: PciExtendedCapabilityId, version: u8, next: usize) -> u32 {
+    u32::from(u16::from(id))
+        | (u32::from(version) & VERSION_MASK) << VERSION_SHIFT
+        | (next as u32) << NEXT_SHIFT
+}
+
+/// Location of an extended capability in the configuration space.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ExtendedCapabilityRegion {
+    /// ID of the capability.
+    pub id: PciExtendedCapabilityId,
+    /// Version of the capability.
+    pub version: u8,
+    /// Offset of the capability in the configuration space.
+    pub offset: usize,
+}
+
+impl ExtendedCapabilityRegion {
+    /// Returns whether the crate knows the version of the capability.
+    ///
+    /// A capability with a newer version than the crate knows is still
+    /// listed, since later versions usually extend earlier ones, but callers
+    /// may not be able to interpret all of it.
+    pub fn is_version_known(&self) -> bool {
+        self.id
+            .max_known_version()
+            .map_or(false, |max| self.version <= max)
+    }
+}
+
+pub(crate) fn walk<C: PciConfig + ?Sized>(cfg: &C) -> Result<Vec<ExtendedCapabilityRegion>> {
+    let mut regions = Vec::new();
+    let mut visited = [false; PCIE_CONFIG_SPACE_SIZE / 4];
+    let mut offset = EXTENDED_CAPABILITIES_START_OFFSET;
+    // Conventional functions have no extended configuration space.
+    if cfg.size() <= EXTENDED_CAPABILITIES_START_OFFSET {
+        return Ok(regions);
+    }
+    let mut header = cfg.read_register(offset / 4)?;
+    if header == 0 {
+        return Ok(regions);
+    }
+
+    loop {
+        regions.push(ExtendedCapabilityRegion {
+            id: PciExtendedCapabilityId::from(header as u16),
+            version: ((header >> VERSION_SHIFT) & VERSION_MASK) as u8,
+            offset,
+        });
+        visited[offset / 4] = true;
+
+        let pointer_offset = offset;
+        offset = (header >> NEXT_SHIFT) as usize & !NEXT_RESERVED_MASK;
+        if offset == 0 {
+            break;
+        }
+        if offset < EXTENDED_CAPABILITIES_START_OFFSET || visited[offset / 4] {
+            return Err(Error::CapabilityListCorrupt(pointer_offset));
+        }
+        header = cfg.read_register(offset / 4)?;
+    }
+    Ok(regions)
+}
@@ -0,0 +1,241 @@
+//! Capabilities hidden from the guest.
+//!
+//! A VMM may expose a device without some of its capabilities, for instance
+//! hiding MSI to force the guest to use INTx. [`HiddenCapabilities`] leaves
+//! the backing capability list untouched and patches the guest reads instead:
+//! the Capabilities Pointer and the next pointers skip over the hidden
+//! capabilities, so a guest walking the list never finds them.
+
+use std::cell::RefCell;
+use std::collections::BTreeSet;
+use std::iter;
+
+use crate::capability::{PciCapabilityId, NEXT_POINTER_OFFSET};
+use crate::device::CAPABILITIES_POINTER_OFFSET;
+use crate::pci_config::{AccessWidth, PciConfig, Result, STATUS_CAPABILITIES_LIST, STATUS_OFFSET};
+use crate::reset::{ResetKind, ResetPolicy};
+
+// `(pointer offset, target offset)` pairs of the list as seen by the guest, or
+// `None` if nothing is hidden.
+type PatchedPointers = Option<Vec<(usize, usize)>>;
+
+/// Configuration space whose capability list, as read by the guest, skips
+/// some capabilities.
+pub struct HiddenCapabilities<T: PciConfig> {
+    inner: T,
+    hidden: BTreeSet<PciCapabilityId>,
+    // Pointers as returned by `patched_pointers`, or `None` when they must be
+    // computed again.
+    patched: RefCell<Option<PatchedPointers>>,
+}
+
+impl<T: PciConfig> HiddenCapabilities<T> {
+    /// Wraps `inner` without hiding any capability.
+    pub fn new(inner: T) -> Self {
+        HiddenCapabilities {
+            inner,
+            hidden: BTreeSet::new(),
+            patched: RefCell::new(Some(None)),
+        }
+    }
+
+    /// Hides the capabilities with ID `id`, all of them if there are several.
+    ///
+    /// If every capability is hidden, the Capabilities List bit of the Status
+    /// register reads as 0.
+    pub fn hide(&mut self, id: PciCapabilityId) {
+        self.hidden.insert(id);
+        self.update_patched_pointers();
+    }
+
+    /// Shows the capabilities with ID `id` again.
+    pub fn show(&mut self, id: PciCapabilityId) {
+        self.hidden.remove(&id);
+        self.update_patched_pointers();
+    }
+
+    /// Returns whether the capabilities with ID `id` are hidden.
+    pub fn is_hidden(&self, id: PciCapabilityId) -> bool {
+        self.hidden.contains(&id)
+    }
+
+    /// Returns the backing configuration space.
+    ///
+    /// Its capability list includes the hidden capabilities.
+    pub fn inner(&self) -> &T {
+        &self.inner
+    }
+
+    /// Returns the backing configuration space mutably.
+    ///
+    /// The list seen by the guest is computed again on the next read, to
+    /// follow changes to the backing capability list.
+    pub fn inner_mut(&mut self) -> &mut T {
+        *self.patched.get_mut() = None;
+        &mut self.inner
+    }
+
+    /// Consumes the wrapper and returns the backing configuration space.
+    pub fn into_inner(self) -> T {
+        self.inner
+    }
+
+    // Computes the pointers again after the hidden capabilities changed.
+    fn update_patched_pointers(&mut self) {
+        let pointers = self.patched_pointers();
+        *self.patched.get_mut() = Some(pointers);
+    }
+
+    // Returns the value of the pointers of the list as seen by the guest.
+    fn patched_pointers(&self) -> PatchedPointers {
+        if self.hidden.is_empty() {
+            return None;
+        }
+        // A corrupt list is left for the guest to deal with.
+        let regions = self.inner.capabilities().ok()?;
+        if !regions.iter().any(|region| self.is_hidden(region.id)) {
+            return None;
+        }
+        let visible: Vec<usize> = regions
+            .iter()
+            .filter(|region| !self.is_hidden(region.id))
+            .map(|region| region.offset)
+            .collect();
+        let pointers = iter::once(CAPABILITIES_POINTER_OFFSET)
+            .chain(visible.iter().map(|offset| offset + NEXT_POINTER_OFFSET));
+        let targets = visible.iter().copied().chain(iter::once(0));
+        Some(pointers.zip(targets).collect())
+    }
+}
+
+impl<T: PciConfig> PciConfig for HiddenCapabilities<T> {
+    fn read_register(&self, reg_idx: usize) -> Result<u32> {
+        let mut value = self.inner.read_register(reg_idx)?;
+        let mut patched = self.patched.borrow_mut();
+        let pointers = match patched.get_or_insert_with(|| self.patched_pointers()) {
+            Some(pointers) => pointers,
+            None => return Ok(value),
+        };
+        for (pointer, target) in pointers.iter().filter(|(p, _)| p / 4 == reg_idx) {
+            let shift = (pointer % 4) * 8;
+            value = (value & !(0xff << shift)) | ((*target as u32) << shift);
+        }
+        if pointers.len() == 1 && reg_idx == STATUS_OFFSET / 4 {
+            let shift = (STATUS_OFFSET % 4) * 8;
+            value &= !(u32::from(STATUS_CAPABILITIES_LIST) << shift);
+        }
+        Ok(value)
+    }
+
+    // A guest read-modify-write of a register holding a patched pointer
+    // writes back the patched value, so the backing bytes are restored before
+    // forwarding the write. The write may still change the list, for
+    // instance through a capability ID, so the pointers are computed again.
+    fn write_register(&mut self, reg_idx: usize, mut value: u32) -> Result<()> {
+        let pointers = self
+            .patched
+            .get_mut()
+            .take()
+            .unwrap_or_else(|| self.patched_pointers());
+        if let Some(pointers) = pointers {
+            let mut kept = pointers
+                .iter()
+                .filter(|(p, _)| p / 4 == reg_idx)
+                .fold(0, |kept, (pointer, _)| kept | 0xff << ((pointer % 4) * 8));
+            if pointers.len() == 1 && reg_idx == STATUS_OFFSET / 4 {
+                kept |= u32::from(STATUS_CAPABILITIES_LIST) << ((STATUS_OFFSET % 4) * 8);
+            }
+            if kept != 0 {
+                let old = self.inner.read_register(reg_idx)?;
+                value = (value & !kept) | (old & kept);
+            }
+        }
+        self.inner.write_register(reg_idx, value)
+    }
+
+    fn size(&self) -> usize {
+        self.inner.size()
+    }
+
+    fn register_access_policy(&self, reg_idx: usize) -> AccessWidth {
+        self.inner.register_access_policy(reg_idx)
+    }
+
+    // The reset may change the backing list, so the pointers are computed
+    // again.
+    fn apply_reset(&mut self, kind: ResetKind, policy: &ResetPolicy) -> Result<()> {
+        *self.patched.get_mut() = None;
+        self.inner.apply_reset(kind, policy)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config_space::ConfigSpace;
+    use crate::msi::MsiCap;
+    use crate::power_management::PowerManagementCap;
+
+    #[test]
+    fn test_hidden_list() {
+        let mut config = ConfigSpace::minimal(0x1234, 0x5678, 0x02, 0x00);
+        let pm = PowerManagementCap::default();
+        let msi = MsiCap::default();
+        config.install_capabilities(&[&msi, &pm]).unwrap();
+
+        let mut hidden = HiddenCapabilities::new(config);
+        hidden.hide(PciCapabilityId::Msi);
+        let offsets: Vec<usize> = hidden
+            .capabilities()
+            .unwrap()
+            .iter()
+            .map(|r| r.offset)
+            .collect();
+        assert_eq!(offsets.len(), 1);
+        assert_eq!(
+            hidden.read_byte(CAPABILITIES_POINTER_OFFSET).unwrap() as usize,
+            offsets[0]
+        );
+
+        hidden
+            .inner_mut()
+            .install_capabilities(&[&pm, &msi])
+            .unwrap();
+        assert_eq!(hidden.capabilities().unwrap().len(), 1);
+        assert_eq!(hidden.read_byte(CAPABILITIES_POINTER_OFFSET).unwrap(), 0x40);
+
+        hidden.hide(PciCapabilityId::PowerManagement);
+        assert!(hidden.capabilities().unwrap().is_empty());
+        assert_eq!(
+            hidden.read_word(STATUS_OFFSET).unwrap() & STATUS_CAPABILITIES_LIST,
+            0
+        );
+
+        hidden.show(PciCapabilityId::Msi);
+        hidden.show(PciCapabilityId::PowerManagement);
+        assert_eq!(hidden.capabilities().unwrap().len(), 2);
+    }
+
+    #[test]
+    fn test_guest_write_keeps_backing_list() {
+        let mut config = ConfigSpace::minimal(0x1234, 0x5678, 0x02, 0x00);
+        let msi = MsiCap::default();
+        let pm = PowerManagementCap::default();
+        config.install_capabilities(&[&msi, &pm]).unwrap();
+        let mut hidden = HiddenCapabilities::new(config);
+        hidden.hide(PciCapabilityId::PowerManagement);
+
+        // Enable MSI: the register also holds the patched next pointer.
+        let control = hidden.read_word(0x42).unwrap();
+        hidden.write_word(0x42, control | 1).unwrap();
+        assert_eq!(hidden.inner().read_word(0x42).unwrap(), control | 1);
+        assert_eq!(hidden.inner().capabilities().unwrap().len(), 2);
+        assert_eq!(hidden.capabilities().unwrap().len(), 1);
+
+        // Same for the Capabilities Pointer.
+        hidden.hide(PciCapabilityId::Msi);
+        hidden.write_byte(CAPABILITIES_POINTER_OFFSET, 0).unwrap();
+        hidden.write_word(STATUS_OFFSET, 0).unwrap();
+        assert_eq!(hidden.inner().capabilities().unwrap().len(), 2);
+    }
+}
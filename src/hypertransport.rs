@@ -0,0 +1,142 @@
+//! HyperTransport capability.
+//!
+//! HyperTransport overloads a single capability ID with many capability
+//! types, told apart by the upper bits of the Command register. Slave/Primary
+//! and Host/Secondary interface blocks use the upper 3 bits, the other types
+//! the upper 5 bits.
+
+use crate::capability::{read_u16, write_u16, PciCapability, PciCapabilityId};
+
+/// Offset of the Command register.
+pub const COMMAND_OFFSET: usize = 0x02;
+
+/// Upper Command register bits holding the type of interface blocks.
+pub const COMMAND_TYPE_3BIT_MASK: u16 = 0xE000;
+/// Upper Command register bits holding the type of the other capabilities.
+pub const COMMAND_TYPE_5BIT_MASK: u16 = 0xF800;
+
+/// Type of a HyperTransport capability.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum HyperTransportCapType {
+    /// Slave/Primary interface block.
+    SlavePrimary,
+    /// Host/Secondary interface block.
+    HostSecondary,
+    /// Interrupt discovery and configuration.
+    Interrupt,
+    /// UnitID clumping.
+    UnitIdClumping,
+    /// Extended configuration space access.
+    ExtendedConfig,
+    /// Address mapping.
+    AddressMapping,
+    /// MSI mapping.
+    MsiMapping,
+    /// Direct route.
+    DirectRoute,
+    /// Virtual channel set.
+    VcSet,
+    /// Error retry.
+    ErrorRetry,
+    /// HyperTransport 3 link configuration.
+    Gen3,
+    /// Function level power management.
+    PowerManagement,
+    /// Type not defined by the specification, holding the upper 5 bits of the
+    /// Command register.
+    Unknown(u8),
+}
+
+impl HyperTransportCapType {
+    /// Decodes the type from the Command register. Lower bits are ignored.
+    pub fn from_command(command: u16) -> Self {
+        match command & COMMAND_TYPE_3BIT_MASK {
+            0x0000 => return HyperTransportCapType::SlavePrimary,
+            0x2000 => return HyperTransportCapType::HostSecondary,
+            _ => (),
+        }
+        match command & COMMAND_TYPE_5BIT_MASK {
+            0x8000 => HyperTransportCapType::Interrupt,
+            0x9000 => HyperTransportCapType::UnitIdClumping,
+            0x9800 => HyperTransportCapType::ExtendedConfig,
+            0xA000 => HyperTransportCapType::AddressMapping,
+            0xA800 => HyperTransportCapType::MsiMapping,
+            0xB000 => HyperTransportCapType::DirectRoute,
+            0xB800 => HyperTransportCapType::VcSet,
+            0xC000 => HyperTransportCapType::ErrorRetry,
+            0xD000 => HyperTransportCapType::Gen3,
+            0xE000 => HyperTransportCapType::PowerManagement,
+            bits => HyperTransportCapType::Unknown((bits >> 11) as u8),
+        }
+    }
+
+    /// Returns the upper Command register bits encoding the type, and the
+    /// mask of these bits.
+    pub fn to_command(self) -> (u16, u16) {
+        let bits = match self {
+            HyperTransportCapType::SlavePrimary => return (0x0000, COMMAND_TYPE_3BIT_MASK),
+            HyperTransportCapType::HostSecondary => return (0x2000, COMMAND_TYPE_3BIT_MASK),
+            HyperTransportCapType::Interrupt => 0x8000,
+            HyperTransportCapType::UnitIdClumping => 0x9000,
+            HyperTransportCapType::ExtendedConfig => 0x9800,
+            HyperTransportCapType::AddressMapping => 0xA000,
+            HyperTransportCapType::MsiMapping => 0xA800,
+            HyperTransportCapType::DirectRoute => 0xB000,
+            HyperTransportCapType::VcSet => 0xB800,
+            HyperTransportCapType::ErrorRetry => 0xC000,
+            HyperTransportCapType::Gen3 => 0xD000,
+            HyperTransportCapType::PowerManagement => 0xE000,
+            HyperTransportCapType::Unknown(bits) => u16::from(bits) << 11,
+        };
+        (bits & COMMAND_TYPE_5BIT_MASK, COMMAND_TYPE_5BIT_MASK)
+    }
+}
+
+/// HyperTransport capability.
+///
+/// Only the header is decoded; the registers following the Command register
+/// depend on the capability type.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct HyperTransportCap {
+    /// Command register.
+    pub command: u16,
+    /// Bytes following the Command register.
+    pub data: Vec<u8>,
+}
+
+impl HyperTransportCap {
+    /// ID of the capability.
+    pub const ID: PciCapabilityId = PciCapabilityId::HyperTransport;
+
+    /// Returns the type of the capability.
+    pub fn cap_type(&self) -> HyperTransportCapType {
+        HyperTransportCapType::from_command(self.command)
+    }
+
+    /// Sets the type of the capability, leaving the other Command register
+    /// bits untouched.
+    pub fn set_cap_type(&mut self, cap_type: HyperTransportCapType) {
+        let (bits, mask) = cap_type.to_command();
+        self.command = (self.command & !mask) | bits;
+    }
+
+    pub(crate) fn from_bytes(bytes: &[u8]) -> Self {
+        HyperTransportCap {
+            command: read_u16(bytes, COMMAND_OFFSET),
+            data: bytes[COMMAND_OFFSET + 2..].to_vec(),
+        }
+    }
+}
+
+impl PciCapability for HyperTransportCap {
+    fn id(&self) -> PciCapabilityId {
+        Self::ID
+    }
+
+    fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = vec![0; COMMAND_OFFSET + 2];
+        write_u16(&mut bytes, COMMAND_OFFSET, self.command);
+        bytes.extend_from_slice(&self.data);
+        bytes
+    }
+}
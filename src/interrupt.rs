@@ -0,0 +1,140 @@
+//! Interrupt mode of a function.
+//!
+//! A function signals interrupts with INTx, MSI or MSI-X, depending on the
+//! Interrupt Disable bit of the Command register and on the enable bits of
+//! its MSI and MSI-X capabilities. MSI-X takes precedence over MSI, which
+//! takes precedence over INTx.
+//!
+//! INTx pins behind a PCI-to-PCI bridge are rotated by the device number
+//! before reaching the primary bus, so that devices on the secondary bus
+//! don't all share INTA#.
+
+use std::collections::BTreeMap;
+use std::ops::RangeInclusive;
+
+use crate::capability::PciCapabilityId;
+use crate::device::INTERRUPT_PIN_OFFSET;
+use crate::pci_config::{PciConfig, Result, COMMAND_INTERRUPT_DISABLE, COMMAND_OFFSET};
+use crate::{msi, msix};
+
+// Valid values of the Interrupt Pin register for a function using INTx.
+const INTX_PINS: RangeInclusive<u8> = 1..=4;
+
+/// Mechanism a function signals its interrupts with.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum InterruptMode {
+    /// The function can't signal interrupts: it has no INTx pin, or INTx is
+    /// disabled, and neither MSI nor MSI-X is enabled.
+    Disabled,
+    /// Legacy INTx interrupts.
+    Intx,
+    /// Message Signaled Interrupts.
+    Msi,
+    /// MSI-X interrupts.
+    MsiX,
+}
+
+/// Returns the pin on the primary bus of a bridge that INTx pin `pin` of
+/// device `device` on the secondary bus is routed to.
+///
+/// Pins are numbered as in the Interrupt Pin register, 1 for INTA# to 4 for
+/// INTD#. Returns `None` for other values, 0 meaning that the function uses
+/// no INTx pin.
+pub fn swizzle_interrupt_pin(device: u8, pin: u8) -> Option<u8> {
+    if !INTX_PINS.contains(&pin) {
+        return None;
+    }
+    Some(swizzle(device, pin))
+}
+
+/// Returns, for each `(device, pin)` of `devices`, the `(device, pin)` it is
+/// routed to on the root bus.
+///
+/// The devices sit behind the bridges of `bridge_path`, given by device
+/// number from the root bus down: the first bridge is on the root bus and
+/// the devices are on the secondary bus of the last one. The platform then
+/// maps the pins of the root bus devices to interrupt lines. Devices without
+/// a valid pin are left out.
+pub fn route_interrupts(bridge_path: &[u8], devices: &[(u8, u8)]) -> BTreeMap<(u8, u8), (u8, u8)> {
+    devices
+        .iter()
+        .filter(|(_, pin)| INTX_PINS.contains(pin))
+        .map(|&(device, pin)| {
+            let routed = bridge_path
+                .iter()
+                .rev()
+                .fold((device, pin), |(device, pin), &bridge| {
+                    (bridge, swizzle(device, pin))
+                });
+            ((device, pin), routed)
+        })
+        .collect()
+}
+
+// Rotates the valid INTx pin `pin` by the device number `device`.
+fn swizzle(device: u8, pin: u8) -> u8 {
+    (pin - 1 + device % 4) % 4 + 1
+}
+
+pub(crate) fn active_mode<C: PciConfig + ?Sized>(cfg: &C) -> Result<InterruptMode> {
+    if capability_enabled(cfg, PciCapabilityId::MsiX, msix::CONTROL_ENABLE)? {
+        return Ok(InterruptMode::MsiX);
+    }
+    if capability_enabled(cfg, PciCapabilityId::Msi, msi::CONTROL_ENABLE)? {
+        return Ok(InterruptMode::Msi);
+    }
+    let intx_disabled = cfg.read_word(COMMAND_OFFSET)? & COMMAND_INTERRUPT_DISABLE != 0;
+    if intx_disabled || cfg.read_byte(INTERRUPT_PIN_OFFSET)? == 0 {
+        return Ok(InterruptMode::Disabled);
+    }
+    Ok(InterruptMode::Intx)
+}
+
+// Returns whether the first capability with ID `id` has the `enable` bit of
+// its Message Control register set. MSI and MSI-X share the location of that
+// register.
+fn capability_enabled<C: PciConfig + ?Sized>(
+    cfg: &C,
+    id: PciCapabilityId,
+    enable: u16,
+) -> Result<bool> {
+    match cfg.find_capability(id)? {
+        Some(offset) => Ok(cfg.read_cap_word(offset, msi::CONTROL_OFFSET)? & enable != 0),
+        None => Ok(false),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_swizzle_table() {
+        // Table of the PCI-to-PCI Bridge specification: the primary bus pin,
+        // by device number modulo 4 and secondary bus pin INTA# to INTD#.
+        let table = [[1, 2, 3, 4], [2, 3, 4, 1], [3, 4, 1, 2], [4, 1, 2, 3]];
+        for device in 0..32u8 {
+            for pin in INTX_PINS {
+                assert_eq!(
+                    swizzle_interrupt_pin(device, pin),
+                    Some(table[usize::from(device % 4)][usize::from(pin - 1)])
+                );
+            }
+        }
+        assert_eq!(swizzle_interrupt_pin(1, 0), None);
+        assert_eq!(swizzle_interrupt_pin(1, 5), None);
+    }
+
+    #[test]
+    fn test_route_interrupts() {
+        let routes = route_interrupts(&[2, 5], &[(1, 1), (4, 2), (3, 0)]);
+        assert_eq!(routes.len(), 2);
+        // INTA# of device 1 is INTB# of bridge 5, then INTC# of bridge 2.
+        assert_eq!(routes[&(1, 1)], (2, 3));
+        // INTB# of device 4 is INTB# of bridge 5, then INTC# of bridge 2.
+        assert_eq!(routes[&(4, 2)], (2, 3));
+
+        let routes = route_interrupts(&[], &[(3, 4)]);
+        assert_eq!(routes[&(3, 4)], (3, 4));
+    }
+}
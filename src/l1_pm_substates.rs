@@ -0,0 +1,132 @@
+//! L1 PM Substates extended capability.
+
+use crate::capability::write_u32;
+use crate::extended_capability::{PciExtendedCapability, PciExtendedCapabilityId};
+
+/// Offset of the L1 PM Substates Capabilities register.
+pub const CAPABILITIES_OFFSET: usize = 0x04;
+/// Offset of the L1 PM Substates Control 1 register.
+pub const CONTROL_1_OFFSET: usize = 0x08;
+/// Offset of the L1 PM Substates Control 2 register.
+pub const CONTROL_2_OFFSET: usize = 0x0C;
+
+/// Capabilities bit set when PCI-PM L1.2 is supported.
+pub const CAPABILITIES_PCI_PM_L1_2: u32 = 1 << 0;
+/// Capabilities bit set when PCI-PM L1.1 is supported.
+pub const CAPABILITIES_PCI_PM_L1_1: u32 = 1 << 1;
+/// Capabilities bit set when ASPM L1.2 is supported.
+pub const CAPABILITIES_ASPM_L1_2: u32 = 1 << 2;
+/// Capabilities bit set when ASPM L1.1 is supported.
+pub const CAPABILITIES_ASPM_L1_1: u32 = 1 << 3;
+/// Capabilities bit set when the L1 PM Substates are supported.
+pub const CAPABILITIES_L1_PM_SUBSTATES: u32 = 1 << 4;
+/// Control 1 bit enabling PCI-PM L1.2.
+pub const CONTROL_1_PCI_PM_L1_2_ENABLE: u32 = 1 << 0;
+/// Control 1 bit enabling PCI-PM L1.1.
+pub const CONTROL_1_PCI_PM_L1_1_ENABLE: u32 = 1 << 1;
+/// Control 1 bit enabling ASPM L1.2.
+pub const CONTROL_1_ASPM_L1_2_ENABLE: u32 = 1 << 2;
+/// Control 1 bit enabling ASPM L1.1.
+pub const CONTROL_1_ASPM_L1_1_ENABLE: u32 = 1 << 3;
+
+/// L1 PM Substates extended capability.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct L1PmSubstatesCap {
+    /// L1 PM Substates Capabilities register.
+    pub capabilities: u32,
+    /// L1 PM Substates Control 1 register.
+    pub control1: u32,
+    /// L1 PM Substates Control 2 register.
+    pub control2: u32,
+}
+
+impl L1PmSubstatesCap {
+    /// ID of the extended capability.
+    pub const ID: PciExtendedCapabilityId = PciExtendedCapabilityId::L1PmSubstates;
+    /// Version of the extended capability.
+    pub const VERSION: u8 = 1;
+    /// Length of the extended capability in bytes.
+    pub const LEN: usize = 0x10;
+
+    /// Creates a capability advertising no supported substate.
+    ///
+    /// This is enough for guests that expect the capability on PCI Express
+    /// links but don't need to manage the link power.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns whether the L1 PM Substates are supported.
+    pub fn substates_supported(&self) -> bool {
+        self.capabilities & CAPABILITIES_L1_PM_SUBSTATES != 0
+    }
+}
+
+impl PciExtendedCapability for L1PmSubstatesCap {
+    fn id(&self) -> PciExtendedCapabilityId {
+        Self::ID
+    }
+
+    fn version(&self) -> u8 {
+        Self::VERSION
+    }
+
+    fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = vec![0; Self::LEN];
+        write_u32(&mut bytes, CAPABILITIES_OFFSET, self.capabilities);
+        write_u32(&mut bytes, CONTROL_1_OFFSET, self.control1);
+        write_u32(&mut bytes, CONTROL_2_OFFSET, self.control2);
+        bytes
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ats::AtsCap;
+    use crate::config_space::ConfigSpace;
+    use crate::extended_capability::ExtendedCapabilityRegion;
+    use crate::pci_config::PciConfig;
+
+    #[test]
+    fn test_install() {
+        let mut config = ConfigSpace::new_pcie();
+        let ats = AtsCap::default();
+        let l1 = L1PmSubstatesCap {
+            capabilities: CAPABILITIES_L1_PM_SUBSTATES | CAPABILITIES_ASPM_L1_1,
+            ..L1PmSubstatesCap::new()
+        };
+        assert_eq!(
+            config.install_extended_capabilities(&[&ats, &l1]).unwrap(),
+            vec![0x100, 0x108]
+        );
+
+        assert_eq!(
+            config.extended_capabilities().unwrap(),
+            vec![
+                ExtendedCapabilityRegion {
+                    id: AtsCap::ID,
+                    version: AtsCap::VERSION,
+                    offset: 0x100,
+                },
+                ExtendedCapabilityRegion {
+                    id: L1PmSubstatesCap::ID,
+                    version: L1PmSubstatesCap::VERSION,
+                    offset: 0x108,
+                },
+            ]
+        );
+        assert_eq!(config.read_register(0x100 / 4).unwrap(), 0x1081_000F);
+        assert_eq!(config.read_register(0x108 / 4).unwrap(), 0x0001_001E);
+        assert_eq!(
+            config
+                .read_register((0x108 + CAPABILITIES_OFFSET) / 4)
+                .unwrap(),
+            0x0000_0018
+        );
+        assert!(config.extended_capabilities().unwrap()[1].is_version_known());
+
+        config.write_register(0x100 / 4, 0).unwrap();
+        assert_eq!(config.extended_capabilities().unwrap().len(), 2);
+    }
+}
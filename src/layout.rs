@@ -0,0 +1,180 @@
+//! Names and extents of the configuration header fields.
+
+use crate::pci_config::{self, PciConfig, PciHeaderType, Result};
+use crate::{bridge, cardbus, device};
+
+// Fields shared by all header types, as `(name, offset, length)`.
+const COMMON_FIELDS: &[(&str, usize, usize)] = &[
+    ("Vendor ID", pci_config::VENDOR_ID_OFFSET, 2),
+    ("Device ID", pci_config::DEVICE_ID_OFFSET, 2),
+    ("Command", pci_config::COMMAND_OFFSET, 2),
+    ("Status", pci_config::STATUS_OFFSET, 2),
+    ("Revision ID", pci_config::REVISION_ID_OFFSET, 1),
+    ("Programming Interface", pci_config::PROG_IF_OFFSET, 1),
+    ("Subclass", pci_config::SUBCLASS_OFFSET, 1),
+    ("Class Code", pci_config::CLASS_CODE_OFFSET, 1),
+    ("Cache Line Size", pci_config::CACHE_LINE_SIZE_OFFSET, 1),
+    ("Latency Timer", pci_config::LATENCY_TIMER_OFFSET, 1),
+    ("Header Type", pci_config::HEADER_TYPE_OFFSET, 1),
+    ("BIST", pci_config::BIST_OFFSET, 1),
+];
+
+const DEVICE_FIELDS: &[(&str, usize, usize)] = &[
+    ("BAR0", device::BARS_START_OFFSET, 4),
+    ("BAR1", device::BARS_START_OFFSET + 0x04, 4),
+    ("BAR2", device::BARS_START_OFFSET + 0x08, 4),
+    ("BAR3", device::BARS_START_OFFSET + 0x0C, 4),
+    ("BAR4", device::BARS_START_OFFSET + 0x10, 4),
+    ("BAR5", device::BARS_START_OFFSET + 0x14, 4),
+    ("CardBus CIS Pointer", device::CARDBUS_CIS_OFFSET, 4),
+    ("Subsystem Vendor ID", device::SUBSYSTEM_VENDOR_ID_OFFSET, 2),
+    ("Subsystem ID", device::SUBSYSTEM_ID_OFFSET, 2),
+    ("Expansion ROM Base Address", device::ROM_BAR_OFFSET, 4),
+    (
+        "Capabilities Pointer",
+        device::CAPABILITIES_POINTER_OFFSET,
+        1,
+    ),
+    ("Interrupt Line", device::INTERRUPT_LINE_OFFSET, 1),
+    ("Interrupt Pin", device::INTERRUPT_PIN_OFFSET, 1),
+    ("Min_Gnt", device::MIN_GRANT_OFFSET, 1),
+    ("Max_Lat", device::MAX_LATENCY_OFFSET, 1),
+];
+
+const BRIDGE_FIELDS: &[(&str, usize, usize)] = &[
+    ("BAR0", bridge::BARS_START_OFFSET, 4),
+    ("BAR1", bridge::BARS_START_OFFSET + 0x04, 4),
+    ("Primary Bus Number", bridge::PRIMARY_BUS_OFFSET, 1),
+    ("Secondary Bus Number", bridge::SECONDARY_BUS_OFFSET, 1),
+    ("Subordinate Bus Number", bridge::SUBORDINATE_BUS_OFFSET, 1),
+    (
+        "Secondary Latency Timer",
+        bridge::SECONDARY_LATENCY_TIMER_OFFSET,
+        1,
+    ),
+    ("I/O Base", bridge::IO_BASE_OFFSET, 1),
+    ("I/O Limit", bridge::IO_LIMIT_OFFSET, 1),
+    ("Secondary Status", bridge::SECONDARY_STATUS_OFFSET, 2),
+    ("Memory Base", bridge::MEMORY_BASE_OFFSET, 2),
+    ("Memory Limit", bridge::MEMORY_LIMIT_OFFSET, 2),
+    (
+        "Prefetchable Memory Base",
+        bridge::PREFETCHABLE_MEMORY_BASE_OFFSET,
+        2,
+    ),
+    (
+        "Prefetchable Memory Limit",
+        bridge::PREFETCHABLE_MEMORY_LIMIT_OFFSET,
+        2,
+    ),
+    (
+        "Prefetchable Base Upper 32 Bits",
+        bridge::PREFETCHABLE_BASE_UPPER_OFFSET,
+        4,
+    ),
+    (
+        "Prefetchable Limit Upper 32 Bits",
+        bridge::PREFETCHABLE_LIMIT_UPPER_OFFSET,
+        4,
+    ),
+    ("I/O Base Upper 16 Bits", bridge::IO_BASE_UPPER_OFFSET, 2),
+    ("I/O Limit Upper 16 Bits", bridge::IO_LIMIT_UPPER_OFFSET, 2),
+    (
+        "Capabilities Pointer",
+        bridge::CAPABILITIES_POINTER_OFFSET,
+        1,
+    ),
+    ("Expansion ROM Base Address", bridge::ROM_BAR_OFFSET, 4),
+    ("Interrupt Line", bridge::INTERRUPT_LINE_OFFSET, 1),
+    ("Interrupt Pin", bridge::INTERRUPT_PIN_OFFSET, 1),
+    ("Bridge Control", bridge::BRIDGE_CONTROL_OFFSET, 2),
+];
+
+const CARDBUS_FIELDS: &[(&str, usize, usize)] = &[
+    (
+        "CardBus Socket/ExCa Base Address",
+        cardbus::SOCKET_BASE_OFFSET,
+        4,
+    ),
+    (
+        "Capabilities Pointer",
+        cardbus::CAPABILITIES_POINTER_OFFSET,
+        1,
+    ),
+    ("Secondary Status", cardbus::SECONDARY_STATUS_OFFSET, 2),
+    ("PCI Bus Number", cardbus::PCI_BUS_OFFSET, 1),
+    ("CardBus Bus Number", cardbus::CARDBUS_BUS_OFFSET, 1),
+    ("Subordinate Bus Number", cardbus::SUBORDINATE_BUS_OFFSET, 1),
+    (
+        "CardBus Latency Timer",
+        cardbus::CARDBUS_LATENCY_TIMER_OFFSET,
+        1,
+    ),
+    ("Memory Base Address 0", cardbus::MEMORY_BASE_0_OFFSET, 4),
+    ("Memory Limit 0", cardbus::MEMORY_LIMIT_0_OFFSET, 4),
+    ("Memory Base Address 1", cardbus::MEMORY_BASE_1_OFFSET, 4),
+    ("Memory Limit 1", cardbus::MEMORY_LIMIT_1_OFFSET, 4),
+    ("I/O Base Address 0", cardbus::IO_BASE_0_OFFSET, 4),
+    ("I/O Limit 0", cardbus::IO_LIMIT_0_OFFSET, 4),
+    ("I/O Base Address 1", cardbus::IO_BASE_1_OFFSET, 4),
+    ("I/O Limit 1", cardbus::IO_LIMIT_1_OFFSET, 4),
+    ("Interrupt Line", cardbus::INTERRUPT_LINE_OFFSET, 1),
+    ("Interrupt Pin", cardbus::INTERRUPT_PIN_OFFSET, 1),
+    ("Bridge Control", cardbus::BRIDGE_CONTROL_OFFSET, 2),
+    ("Subsystem Device ID", cardbus::SUBSYSTEM_ID_OFFSET, 2),
+    (
+        "Subsystem Vendor ID",
+        cardbus::SUBSYSTEM_VENDOR_ID_OFFSET,
+        2,
+    ),
+    (
+        "16-bit PC Card Legacy Mode Base Address",
+        cardbus::LEGACY_MODE_BASE_OFFSET,
+        4,
+    ),
+];
+
+fn type_specific_fields(header: PciHeaderType) -> &'static [(&'static str, usize, usize)] {
+    match header {
+        PciHeaderType::Device => DEVICE_FIELDS,
+        PciHeaderType::PciToPciBridge => BRIDGE_FIELDS,
+        PciHeaderType::PciToCardbusBridge => CARDBUS_FIELDS,
+    }
+}
+
+/// Returns the `(name, offset, length)` of every field of the `header`
+/// layout, by increasing offset.
+///
+/// The fields shared by all header types come first, followed by the ones
+/// of the `header` layout. Reserved bytes aren't part of any field.
+pub fn fields(header: PciHeaderType) -> impl Iterator<Item = (&'static str, usize, usize)> {
+    COMMON_FIELDS
+        .iter()
+        .chain(type_specific_fields(header).iter())
+        .copied()
+}
+
+// Returns the `(name, offset, value)` of every field of the `header` layout,
+// with the values read from `cfg`.
+pub(crate) fn read_fields<C: PciConfig + ?Sized>(
+    cfg: &C,
+    header: PciHeaderType,
+) -> Result<Vec<(&'static str, usize, u64)>> {
+    fields(header)
+        .map(|(name, offset, len)| {
+            let value = (offset..offset + len)
+                .rev()
+                .try_fold(0, |value, o| Ok(value << 8 | u64::from(cfg.read_byte(o)?)))?;
+            Ok((name, offset, value))
+        })
+        .collect()
+}
+
+/// Returns the name of the header field containing the byte at `offset` for
+/// the `header` layout, or `None` if the byte is reserved or outside of the
+/// header.
+pub fn field_at(offset: usize, header: PciHeaderType) -> Option<&'static str> {
+    fields(header)
+        .find(|(_, start, len)| (*start..*start + *len).contains(&offset))
+        .map(|(name, _, _)| name)
+}
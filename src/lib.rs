@@ -1,6 +1,34 @@
 #![deny(missing_docs)]
-//! Dummy crate needs high-level documentation.
-/// Dummy public function needs documentation.
-pub fn it_works() {
-    assert_ne!(0, 1);
-}
+//! Traits and helpers for emulating PCI configuration space.
+//!
+//! The crate models the configuration space as an array of 32-bit registers
+//! (see [`PciConfig`](pci_config/trait.PciConfig.html)) and layers typed
+//! accessors for the standard header fields on top of it.
+
+pub mod address;
+pub mod ats;
+pub mod bar;
+pub mod bridge;
+pub mod capability;
+pub mod cardbus;
+pub mod compact_pci_hot_swap;
+pub mod config_space;
+pub mod device;
+pub mod extended_capability;
+pub mod hidden;
+pub mod hypertransport;
+pub mod interrupt;
+pub mod l1_pm_substates;
+pub mod layout;
+pub mod msi;
+pub mod msix;
+pub mod msix_control;
+pub mod multifunction;
+pub mod notify;
+pub mod parse;
+pub mod pci_config;
+pub mod pci_express;
+pub mod power_management;
+pub mod reset;
+pub mod shadow;
+pub mod sync;
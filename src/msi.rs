@@ -0,0 +1,228 @@
+//! Message Signaled Interrupts (MSI) capability.
+
+use crate::capability::{
+    self, read_u16, read_u32, write_u16, write_u32, PciCapability, PciCapabilityId,
+};
+use crate::pci_config::{self, PciConfig};
+
+/// Offset of the Message Control register.
+pub const CONTROL_OFFSET: usize = 0x02;
+/// Offset of the Message Address register.
+pub const ADDRESS_LO_OFFSET: usize = 0x04;
+
+/// Message Control bit enabling MSI.
+pub const CONTROL_ENABLE: u16 = 1 << 0;
+/// Message Control bit set when the function supports 64-bit addresses.
+pub const CONTROL_64BIT: u16 = 1 << 7;
+/// Message Control bit set when the function supports per-vector masking.
+pub const CONTROL_PER_VECTOR_MASK: u16 = 1 << 8;
+
+/// Maximum number of vectors of a function.
+pub const MAX_VECTORS: usize = 32;
+
+/// MSI capability.
+///
+/// The layout of the capability depends on the 64-bit and per-vector masking
+/// bits of the Message Control register; fields that aren't part of the
+/// layout are 0.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct MsiCap {
+    /// Message Control register.
+    pub msg_ctl: u16,
+    /// Lower 32 bits of the Message Address register.
+    pub msg_addr_lo: u32,
+    /// Upper 32 bits of the Message Address register.
+    pub msg_addr_hi: u32,
+    /// Message Data register.
+    pub msg_data: u16,
+    /// Mask Bits register.
+    pub mask_bits: u32,
+    /// Pending Bits register.
+    pub pending_bits: u32,
+}
+
+impl MsiCap {
+    /// ID of the capability.
+    pub const ID: PciCapabilityId = PciCapabilityId::Msi;
+
+    /// Returns the length in bytes of a capability with the `control` Message
+    /// Control register.
+    pub fn len_for_control(control: u16) -> usize {
+        let mut len = 0x0A;
+        if control & CONTROL_64BIT != 0 {
+            len += 4;
+        }
+        if control & CONTROL_PER_VECTOR_MASK != 0 {
+            len += 10;
+        }
+        len
+    }
+
+    /// Masks the vector `vector`. Vectors past `MAX_VECTORS` are ignored.
+    ///
+    /// The mask and pending bits are only part of the capability when
+    /// per-vector masking is supported.
+    pub fn mask_vector(&mut self, vector: usize) {
+        if vector < MAX_VECTORS {
+            self.mask_bits |= 1 << vector;
+        }
+    }
+
+    /// Unmasks the vector `vector`. Vectors past `MAX_VECTORS` are ignored.
+    ///
+    /// A pending interrupt of the vector is to be delivered, see
+    /// [`take_pending`](#method.take_pending).
+    pub fn unmask_vector(&mut self, vector: usize) {
+        if vector < MAX_VECTORS {
+            self.mask_bits &= !(1 << vector);
+        }
+    }
+
+    /// Returns whether the vector `vector` is masked.
+    pub fn is_vector_masked(&self, vector: usize) -> bool {
+        vector < MAX_VECTORS && self.mask_bits & (1 << vector) != 0
+    }
+
+    /// Records an interrupt of the vector `vector` that couldn't be delivered
+    /// because the vector is masked. Vectors past `MAX_VECTORS` are ignored.
+    pub fn set_pending(&mut self, vector: usize) {
+        if vector < MAX_VECTORS {
+            self.pending_bits |= 1 << vector;
+        }
+    }
+
+    /// Clears the pending bits of the unmasked vectors and returns these
+    /// vectors, by increasing number.
+    ///
+    /// The VMM calls this after unmasking vectors and delivers an interrupt
+    /// for each vector returned.
+    pub fn take_pending(&mut self) -> Vec<usize> {
+        let ready = self.pending_bits & !self.mask_bits;
+        self.pending_bits &= !ready;
+        (0..MAX_VECTORS)
+            .filter(|vector| ready & (1 << vector) != 0)
+            .collect()
+    }
+
+    /// Reads the capability at `offset` of `cfg`.
+    ///
+    /// Fails with `CapabilityIdMismatch` if the capability there has another
+    /// ID, or `CapabilityListCorrupt` if it doesn't fit in the standard
+    /// configuration space.
+    ///
+    /// The length read depends on the Message Control register.
+    pub fn from_config<C: PciConfig + ?Sized>(cfg: &C, offset: usize) -> pci_config::Result<Self> {
+        let control = cfg.read_cap_word(offset, CONTROL_OFFSET)?;
+        let len = Self::len_for_control(control);
+        let bytes = capability::read_capability(cfg, offset, Self::ID, len)?;
+        Ok(Self::from_bytes(&bytes))
+    }
+
+    pub(crate) fn from_bytes(bytes: &[u8]) -> Self {
+        let msg_ctl = read_u16(bytes, CONTROL_OFFSET);
+        let mut cap = MsiCap {
+            msg_ctl,
+            msg_addr_lo: read_u32(bytes, ADDRESS_LO_OFFSET),
+            ..Default::default()
+        };
+        let mut offset = ADDRESS_LO_OFFSET + 4;
+        if msg_ctl & CONTROL_64BIT != 0 {
+            cap.msg_addr_hi = read_u32(bytes, offset);
+            offset += 4;
+        }
+        cap.msg_data = read_u16(bytes, offset);
+        offset += 4;
+        if msg_ctl & CONTROL_PER_VECTOR_MASK != 0 {
+            cap.mask_bits = read_u32(bytes, offset);
+            cap.pending_bits = read_u32(bytes, offset + 4);
+        }
+        cap
+    }
+}
+
+impl PciCapability for MsiCap {
+    fn id(&self) -> PciCapabilityId {
+        Self::ID
+    }
+
+    fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = vec![0; Self::len_for_control(self.msg_ctl)];
+        write_u16(&mut bytes, CONTROL_OFFSET, self.msg_ctl);
+        write_u32(&mut bytes, ADDRESS_LO_OFFSET, self.msg_addr_lo);
+        let mut offset = ADDRESS_LO_OFFSET + 4;
+        if self.msg_ctl & CONTROL_64BIT != 0 {
+            write_u32(&mut bytes, offset, self.msg_addr_hi);
+            offset += 4;
+        }
+        write_u16(&mut bytes, offset, self.msg_data);
+        offset += 4;
+        if self.msg_ctl & CONTROL_PER_VECTOR_MASK != 0 {
+            write_u32(&mut bytes, offset, self.mask_bits);
+            write_u32(&mut bytes, offset + 4, self.pending_bits);
+        }
+        bytes
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_mask_vector() {
+        let mut msi = MsiCap::default();
+        msi.mask_vector(0);
+        msi.mask_vector(31);
+        msi.mask_vector(MAX_VECTORS);
+        assert_eq!(msi.mask_bits, 0x8000_0001);
+        assert!(msi.is_vector_masked(31));
+        assert!(!msi.is_vector_masked(1));
+        assert!(!msi.is_vector_masked(MAX_VECTORS));
+
+        msi.unmask_vector(31);
+        msi.unmask_vector(MAX_VECTORS);
+        assert_eq!(msi.mask_bits, 0x0000_0001);
+    }
+
+    #[test]
+    fn test_pending_delivered_on_unmask() {
+        let mut msi = MsiCap::default();
+        msi.mask_vector(2);
+        msi.mask_vector(5);
+        msi.set_pending(2);
+        msi.set_pending(5);
+        msi.set_pending(MAX_VECTORS);
+        assert_eq!(msi.pending_bits, 0x0000_0024);
+        assert!(msi.take_pending().is_empty());
+
+        msi.unmask_vector(5);
+        assert_eq!(msi.take_pending(), vec![5]);
+        assert_eq!(msi.pending_bits, 0x0000_0004);
+        assert!(msi.take_pending().is_empty());
+
+        msi.unmask_vector(2);
+        assert_eq!(msi.take_pending(), vec![2]);
+        assert_eq!(msi.pending_bits, 0);
+    }
+
+    #[test]
+    fn test_mask_and_pending_bytes() {
+        let mut msi = MsiCap {
+            msg_ctl: CONTROL_64BIT | CONTROL_PER_VECTOR_MASK,
+            ..Default::default()
+        };
+        msi.mask_vector(1);
+        msi.set_pending(1);
+
+        let bytes = msi.to_bytes();
+        assert_eq!(bytes.len(), 0x18);
+        assert_eq!(read_u32(&bytes, 0x10), 0x0000_0002);
+        assert_eq!(read_u32(&bytes, 0x14), 0x0000_0002);
+        assert_eq!(MsiCap::from_bytes(&bytes), msi);
+
+        // Without per-vector masking, the bits aren't part of the layout.
+        msi.msg_ctl = CONTROL_64BIT;
+        assert_eq!(msi.to_bytes().len(), 0x0E);
+        assert_eq!(MsiCap::from_bytes(&msi.to_bytes()).mask_bits, 0);
+    }
+}
@@ -0,0 +1,232 @@
+//! MSI-X capability.
+
+use std::fmt;
+
+use crate::capability::{
+    self, read_u16, read_u32, write_u16, write_u32, PciCapability, PciCapabilityId,
+};
+use crate::device;
+use crate::pci_config::{self, PciConfig};
+
+/// Offset of the Message Control register.
+pub const CONTROL_OFFSET: usize = 0x02;
+/// Offset of the Table Offset/Table BIR register.
+pub const TABLE_OFFSET: usize = 0x04;
+/// Offset of the PBA Offset/PBA BIR register.
+pub const PBA_OFFSET: usize = 0x08;
+
+/// Message Control bits holding the table size minus one.
+pub const CONTROL_TABLE_SIZE_MASK: u16 = 0x7ff;
+/// Message Control bit masking all the vectors of the function.
+pub const CONTROL_FUNCTION_MASK: u16 = 1 << 14;
+/// Message Control bit enabling MSI-X.
+pub const CONTROL_ENABLE: u16 = 1 << 15;
+/// Largest number of entries of an MSI-X table.
+pub const MAX_TABLE_SIZE: u16 = CONTROL_TABLE_SIZE_MASK + 1;
+/// Bits of the Table Offset/Table BIR and PBA Offset/PBA BIR registers
+/// holding the BAR Indicator Register (BIR). The other bits hold the offset
+/// of the structure in that BAR.
+pub const BIR_MASK: u32 = 0x7;
+
+/// Errors related to the MSI-X capability.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Error {
+    /// The MSI-X table can't have this number of entries.
+    TableSizeInvalid(u16),
+    /// The BAR Indicator Register of the MSI-X table or PBA holds this
+    /// value, which doesn't designate a BAR.
+    BirInvalid(u8),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::TableSizeInvalid(size) => write!(f, "invalid MSI-X table size {}", size),
+            Error::BirInvalid(bir) => write!(f, "invalid MSI-X BAR indicator {}", bir),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+/// Result of the MSI-X capability operations.
+pub type Result<T> = std::result::Result<T, Error>;
+
+/// Message Control register of the MSI-X capability.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct MsiXControl(pub u16);
+
+impl MsiXControl {
+    /// Returns the number of entries of the MSI-X table.
+    ///
+    /// The register holds the table size minus one.
+    pub fn table_size(self) -> u16 {
+        (self.0 & CONTROL_TABLE_SIZE_MASK) + 1
+    }
+
+    /// Sets the number of entries of the MSI-X table, from 1 to
+    /// `MAX_TABLE_SIZE`, or fails with `TableSizeInvalid`.
+    pub fn set_table_size(&mut self, size: u16) -> Result<()> {
+        if size == 0 || size > MAX_TABLE_SIZE {
+            return Err(Error::TableSizeInvalid(size));
+        }
+        self.0 = (self.0 & !CONTROL_TABLE_SIZE_MASK) | (size - 1);
+        Ok(())
+    }
+
+    /// Returns whether all the vectors of the function are masked.
+    pub fn function_masked(self) -> bool {
+        self.0 & CONTROL_FUNCTION_MASK != 0
+    }
+
+    /// Masks or unmasks all the vectors of the function.
+    pub fn set_function_masked(&mut self, masked: bool) {
+        self.set_bit(CONTROL_FUNCTION_MASK, masked);
+    }
+
+    /// Returns whether MSI-X is enabled.
+    pub fn enabled(self) -> bool {
+        self.0 & CONTROL_ENABLE != 0
+    }
+
+    /// Enables or disables MSI-X.
+    pub fn set_enabled(&mut self, enabled: bool) {
+        self.set_bit(CONTROL_ENABLE, enabled);
+    }
+
+    // Sets `bit` if `value` is true, clears it otherwise.
+    fn set_bit(&mut self, bit: u16, value: bool) {
+        if value {
+            self.0 |= bit;
+        } else {
+            self.0 &= !bit;
+        }
+    }
+}
+
+/// MSI-X capability.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct MsiXCap {
+    /// Message Control register.
+    pub msg_ctl: u16,
+    /// Table Offset/Table BIR register.
+    pub table: u32,
+    /// PBA Offset/PBA BIR register.
+    pub pba: u32,
+}
+
+impl MsiXCap {
+    /// ID of the capability.
+    pub const ID: PciCapabilityId = PciCapabilityId::MsiX;
+    /// Length of the capability in bytes.
+    pub const LEN: usize = 12;
+
+    /// Returns the Message Control register.
+    pub fn control(&self) -> MsiXControl {
+        MsiXControl(self.msg_ctl)
+    }
+
+    /// Sets the Message Control register.
+    pub fn set_control(&mut self, control: MsiXControl) {
+        self.msg_ctl = control.0;
+    }
+
+    /// Returns the number of entries of the MSI-X table.
+    pub fn table_size(&self) -> u16 {
+        self.control().table_size()
+    }
+
+    /// Returns the index of the BAR holding the MSI-X table and the offset of
+    /// the table in that BAR.
+    ///
+    /// Fails with `BirInvalid` if the BIR is above 5, a reserved value.
+    pub fn table_location(&self) -> Result<(u8, u32)> {
+        decode_location(self.table)
+    }
+
+    /// Returns the index of the BAR holding the Pending Bit Array and the
+    /// offset of the array in that BAR.
+    ///
+    /// Fails with `BirInvalid` if the BIR is above 5, a reserved value.
+    pub fn pba_location(&self) -> Result<(u8, u32)> {
+        decode_location(self.pba)
+    }
+
+    /// Reads the capability at `offset` of `cfg`.
+    ///
+    /// Fails with `CapabilityIdMismatch` if the capability there has another
+    /// ID, or `CapabilityListCorrupt` if it doesn't fit in the standard
+    /// configuration space.
+    pub fn from_config<C: PciConfig + ?Sized>(cfg: &C, offset: usize) -> pci_config::Result<Self> {
+        let bytes = capability::read_capability(cfg, offset, Self::ID, Self::LEN)?;
+        Ok(Self::from_bytes(&bytes))
+    }
+
+    pub(crate) fn from_bytes(bytes: &[u8]) -> Self {
+        MsiXCap {
+            msg_ctl: read_u16(bytes, CONTROL_OFFSET),
+            table: read_u32(bytes, TABLE_OFFSET),
+            pba: read_u32(bytes, PBA_OFFSET),
+        }
+    }
+}
+
+// Splits a Table or PBA register into the BIR and the offset.
+fn decode_location(reg: u32) -> Result<(u8, u32)> {
+    let bir = (reg & BIR_MASK) as u8;
+    if usize::from(bir) >= device::NUM_BARS {
+        return Err(Error::BirInvalid(bir));
+    }
+    Ok((bir, reg & !BIR_MASK))
+}
+
+impl PciCapability for MsiXCap {
+    fn id(&self) -> PciCapabilityId {
+        Self::ID
+    }
+
+    fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = vec![0; Self::LEN];
+        write_u16(&mut bytes, CONTROL_OFFSET, self.msg_ctl);
+        write_u32(&mut bytes, TABLE_OFFSET, self.table);
+        write_u32(&mut bytes, PBA_OFFSET, self.pba);
+        bytes
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_locations() {
+        let msix = MsiXCap {
+            msg_ctl: 0x0007,
+            table: 0x0000_2004,
+            pba: 0x0000_3805,
+        };
+        assert_eq!(msix.table_location(), Ok((4, 0x2000)));
+        assert_eq!(msix.pba_location(), Ok((5, 0x3800)));
+
+        let msix = MsiXCap::from_bytes(&msix.to_bytes());
+        assert_eq!(msix.table_location(), Ok((4, 0x2000)));
+        assert_eq!(msix.pba_location(), Ok((5, 0x3800)));
+    }
+
+    #[test]
+    fn test_locations_invalid_bir() {
+        let msix = MsiXCap {
+            msg_ctl: 0,
+            table: 0xFFFF_FFF8,
+            pba: 0x0000_1006,
+        };
+        assert_eq!(msix.table_location(), Ok((0, 0xFFFF_FFF8)));
+        assert_eq!(msix.pba_location(), Err(Error::BirInvalid(6)));
+
+        let msix = MsiXCap {
+            table: 0x0000_0007,
+            ..msix
+        };
+        assert_eq!(msix.table_location(), Err(Error::BirInvalid(7)));
+    }
+}
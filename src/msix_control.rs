@@ -0,0 +1,166 @@
+//! Guest writes to the MSI-X Message Control register.
+//!
+//! The MSI-X Enable and Function Mask bits decide whether and how the device
+//! delivers its interrupts, so the VMM has to follow guest writes to them.
+//! [`MsiXControlConfig`] filters these writes, keeping the table size and the
+//! reserved bits read-only, and calls a callback when the delivery state
+//! changes.
+
+use crate::msix::{MsiXControl, CONTROL_ENABLE, CONTROL_FUNCTION_MASK, CONTROL_OFFSET};
+use crate::pci_config::{AccessWidth, PciConfig, Result};
+use crate::reset::{ResetKind, ResetPolicy};
+
+/// Callback called with the new Message Control register when the guest
+/// toggles MSI-X Enable or Function Mask.
+pub type ControlCallback = Box<dyn FnMut(MsiXControl) + Send>;
+
+// Message Control bits the guest can write.
+const CONTROL_WRITABLE: u16 = CONTROL_ENABLE | CONTROL_FUNCTION_MASK;
+
+/// Configuration space handling guest writes to the Message Control register
+/// of an MSI-X capability.
+pub struct MsiXControlConfig<T: PciConfig> {
+    inner: T,
+    control_offset: usize,
+    callback: Option<ControlCallback>,
+}
+
+impl<T: PciConfig> MsiXControlConfig<T> {
+    /// Wraps `inner`, whose MSI-X capability is at `cap_offset`.
+    pub fn new(inner: T, cap_offset: usize) -> Self {
+        MsiXControlConfig {
+            inner,
+            control_offset: cap_offset + CONTROL_OFFSET,
+            callback: None,
+        }
+    }
+
+    /// Registers `callback` to be called after each guest write changing
+    /// the MSI-X Enable or Function Mask bits, replacing the previous one.
+    pub fn on_change(&mut self, callback: ControlCallback) {
+        self.callback = Some(callback);
+    }
+
+    /// Returns the current Message Control register.
+    pub fn control(&self) -> Result<MsiXControl> {
+        self.inner.read_word(self.control_offset).map(MsiXControl)
+    }
+
+    /// Returns the backing configuration space.
+    pub fn inner(&self) -> &T {
+        &self.inner
+    }
+
+    /// Returns the backing configuration space mutably.
+    ///
+    /// Writes done through this reference reach the Message Control register
+    /// unfiltered and don't call the callback.
+    pub fn inner_mut(&mut self) -> &mut T {
+        &mut self.inner
+    }
+
+    /// Consumes the wrapper and returns the backing configuration space.
+    pub fn into_inner(self) -> T {
+        self.inner
+    }
+}
+
+impl<T: PciConfig> PciConfig for MsiXControlConfig<T> {
+    fn read_register(&self, reg_idx: usize) -> Result<u32> {
+        self.inner.read_register(reg_idx)
+    }
+
+    fn write_register(&mut self, reg_idx: usize, value: u32) -> Result<()> {
+        if reg_idx != self.control_offset / 4 {
+            return self.inner.write_register(reg_idx, value);
+        }
+        let read_only = u32::from(!CONTROL_WRITABLE) << ((self.control_offset % 4) * 8);
+        let old_control = self.control()?;
+        let old = self.inner.read_register(reg_idx)?;
+        self.inner
+            .write_register(reg_idx, (value & !read_only) | (old & read_only))?;
+        let control = self.control()?;
+        if (control.0 ^ old_control.0) & CONTROL_WRITABLE != 0 {
+            if let Some(callback) = self.callback.as_mut() {
+                callback(control);
+            }
+        }
+        Ok(())
+    }
+
+    fn size(&self) -> usize {
+        self.inner.size()
+    }
+
+    fn register_access_policy(&self, reg_idx: usize) -> AccessWidth {
+        self.inner.register_access_policy(reg_idx)
+    }
+
+    // The reset is done by the device, so it doesn't call the callback.
+    fn apply_reset(&mut self, kind: ResetKind, policy: &ResetPolicy) -> Result<()> {
+        self.inner.apply_reset(kind, policy)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use super::*;
+    use crate::config_space::ConfigSpace;
+    use crate::msix::MsiXCap;
+
+    const CONTROL: usize = 0x40 + CONTROL_OFFSET;
+
+    fn setup() -> (MsiXControlConfig<ConfigSpace>, Arc<Mutex<Vec<MsiXControl>>>) {
+        let mut cfg = ConfigSpace::minimal(0x1234, 0x5678, 0x02, 0x00);
+        let msix = MsiXCap {
+            msg_ctl: 0x0007,
+            table: 0x0000_2000,
+            pba: 0x0000_3000,
+        };
+        cfg.install_capabilities(&[&msix]).unwrap();
+        let mut config = MsiXControlConfig::new(cfg, 0x40);
+        let changes = Arc::new(Mutex::new(Vec::new()));
+        let recorded = changes.clone();
+        config.on_change(Box::new(move |control| {
+            recorded.lock().unwrap().push(control)
+        }));
+        (config, changes)
+    }
+
+    #[test]
+    fn test_table_size_read_only() {
+        let (mut config, changes) = setup();
+        config.write_word(CONTROL, 0x07FF).unwrap();
+        assert_eq!(config.control().unwrap(), MsiXControl(0x0007));
+        assert_eq!(config.control().unwrap().table_size(), 8);
+
+        config.write_word(CONTROL, 0x3FFF | CONTROL_ENABLE).unwrap();
+        assert_eq!(config.control().unwrap(), MsiXControl(0x8007));
+        assert_eq!(*changes.lock().unwrap(), vec![MsiXControl(0x8007)]);
+    }
+
+    #[test]
+    fn test_callback_on_change() {
+        let (mut config, changes) = setup();
+        config.write_word(CONTROL, 0x0007).unwrap();
+        assert!(changes.lock().unwrap().is_empty());
+
+        config.write_word(CONTROL, CONTROL_ENABLE).unwrap();
+        config.write_word(CONTROL, CONTROL_ENABLE).unwrap();
+        config
+            .write_word(CONTROL, CONTROL_ENABLE | CONTROL_FUNCTION_MASK)
+            .unwrap();
+        // Writes elsewhere in the register don't call the callback.
+        config.write_word(0x40, 0xFFFF).unwrap();
+        assert_eq!(
+            *changes.lock().unwrap(),
+            vec![MsiXControl(0x8007), MsiXControl(0xC007)]
+        );
+
+        // Nor do the writes through the backing configuration space.
+        config.inner_mut().write_word(CONTROL, 0x0007).unwrap();
+        assert_eq!(changes.lock().unwrap().len(), 2);
+    }
+}
@@ -0,0 +1,141 @@
+//! Multi-function devices.
+//!
+//! A device exposes up to 8 functions at the same bus and device numbers,
+//! each with its own configuration space. Function 0 is always implemented
+//! and advertises the other functions with the multi-function bit of its
+//! Header Type register.
+
+use std::fmt;
+
+use crate::address::MAX_FUNCTION;
+use crate::pci_config::{self, PciConfig, HEADER_TYPE_MULTIFUNCTION, HEADER_TYPE_OFFSET};
+
+/// Number of functions of a device.
+pub const NUM_FUNCTIONS: usize = MAX_FUNCTION as usize + 1;
+
+/// Errors related to multi-function devices.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Error {
+    /// The function number is out of range, or is 0 which can't be replaced.
+    InvalidFunction(u8),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::InvalidFunction(function) => write!(f, "invalid function number {}", function),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+/// Result of the multi-function device operations.
+pub type Result<T> = std::result::Result<T, Error>;
+
+/// Configuration spaces of the functions of a device.
+///
+/// Accesses through the container are the guest view of the device: absent
+/// functions read as all ones, so their Vendor ID reads as 0xFFFF, and
+/// ignore writes. The multi-function bit of function 0 reads as set as soon
+/// as another function is present.
+pub struct MultiFunctionDevice {
+    functions: Vec<Option<Box<dyn PciConfig>>>,
+}
+
+impl MultiFunctionDevice {
+    /// Creates a single-function device made of `function0`.
+    pub fn new(function0: Box<dyn PciConfig>) -> Self {
+        let mut functions: Vec<Option<Box<dyn PciConfig>>> =
+            (0..NUM_FUNCTIONS).map(|_| None).collect();
+        functions[0] = Some(function0);
+        MultiFunctionDevice { functions }
+    }
+
+    /// Sets the configuration space of function `function`, returning the
+    /// previous one.
+    pub fn add_function(
+        &mut self,
+        function: u8,
+        config: Box<dyn PciConfig>,
+    ) -> Result<Option<Box<dyn PciConfig>>> {
+        if function == 0 || function > MAX_FUNCTION {
+            return Err(Error::InvalidFunction(function));
+        }
+        Ok(self.functions[usize::from(function)].replace(config))
+    }
+
+    /// Removes function `function`, returning its configuration space.
+    pub fn remove_function(&mut self, function: u8) -> Result<Option<Box<dyn PciConfig>>> {
+        if function == 0 || function > MAX_FUNCTION {
+            return Err(Error::InvalidFunction(function));
+        }
+        Ok(self.functions[usize::from(function)].take())
+    }
+
+    /// Returns the configuration space of function `function`, or `None` if
+    /// the function is absent.
+    ///
+    /// This is the function's own view of its configuration space, which
+    /// doesn't include the multi-function bit set by the container.
+    pub fn function(&self, function: u8) -> Option<&dyn PciConfig> {
+        self.functions.get(usize::from(function))?.as_deref()
+    }
+
+    /// Returns the configuration space of function `function` for
+    /// modification, or `None` if the function is absent.
+    pub fn function_mut(&mut self, function: u8) -> Option<&mut (dyn PciConfig + 'static)> {
+        self.functions
+            .get_mut(usize::from(function))?
+            .as_deref_mut()
+    }
+
+    /// Returns whether the device has functions other than function 0.
+    pub fn is_multifunction(&self) -> bool {
+        self.functions[1..].iter().any(Option::is_some)
+    }
+
+    /// Reads `data.len()` bytes at `offset` of function `function`.
+    ///
+    /// Function numbers come from the decoding of ECAM or CF8 addresses. See
+    /// [`PciConfig::read_data`](../pci_config/trait.PciConfig.html#method.read_data)
+    /// for the accepted accesses.
+    pub fn read_data(
+        &self,
+        function: u8,
+        offset: usize,
+        data: &mut [u8],
+    ) -> pci_config::Result<()> {
+        let config = match self.function(function) {
+            Some(config) => config,
+            None => {
+                data.iter_mut().for_each(|byte| *byte = 0xff);
+                return Ok(());
+            }
+        };
+        config.read_data(offset, data)?;
+        if function == 0 && self.is_multifunction() {
+            if let Some(byte) = HEADER_TYPE_OFFSET
+                .checked_sub(offset)
+                .and_then(|idx| data.get_mut(idx))
+            {
+                *byte |= HEADER_TYPE_MULTIFUNCTION;
+            }
+        }
+        Ok(())
+    }
+
+    /// Writes `data` at `offset` of function `function`. Writes to absent
+    /// functions are ignored.
+    pub fn write_data(
+        &mut self,
+        function: u8,
+        offset: usize,
+        data: &[u8],
+    ) -> pci_config::Result<()> {
+        match self.function_mut(function) {
+            Some(config) => config.write_data(offset, data),
+            None => Ok(()),
+        }
+    }
+}
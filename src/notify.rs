@@ -0,0 +1,96 @@
+//! Notification of register writes.
+//!
+//! Device models react to some guest writes: a BAR moving has to be remapped,
+//! a Command register change may toggle decoding or interrupt routing.
+//! [`NotifyConfig`] calls the callbacks registered for a register each time
+//! the register is written.
+
+use std::collections::BTreeMap;
+
+use crate::pci_config::{AccessWidth, PciConfig, Result};
+use crate::reset::{ResetKind, ResetPolicy};
+
+/// Callback called with the old and new values of a written register.
+pub type WriteCallback = Box<dyn FnMut(u32, u32) + Send>;
+
+/// Configuration space calling callbacks on writes to specific registers.
+pub struct NotifyConfig<T: PciConfig> {
+    inner: T,
+    callbacks: BTreeMap<usize, Vec<WriteCallback>>,
+}
+
+impl<T: PciConfig> NotifyConfig<T> {
+    /// Wraps `inner` without any callback.
+    pub fn new(inner: T) -> Self {
+        NotifyConfig {
+            inner,
+            callbacks: BTreeMap::new(),
+        }
+    }
+
+    /// Registers `callback` to be called after each write to the register at
+    /// `reg_idx`.
+    ///
+    /// The callback gets the values of the register before and after the
+    /// write, as read back from the backing configuration space: bits the
+    /// guest can't write keep their old value. Several callbacks registered
+    /// for the same register are called in registration order.
+    pub fn on_write(&mut self, reg_idx: usize, callback: WriteCallback) {
+        self.callbacks.entry(reg_idx).or_default().push(callback);
+    }
+
+    /// Removes the callbacks of the register at `reg_idx`.
+    pub fn clear_callbacks(&mut self, reg_idx: usize) {
+        self.callbacks.remove(&reg_idx);
+    }
+
+    /// Returns the backing configuration space.
+    pub fn inner(&self) -> &T {
+        &self.inner
+    }
+
+    /// Returns the backing configuration space mutably.
+    ///
+    /// Writes done through this reference don't call the callbacks.
+    pub fn inner_mut(&mut self) -> &mut T {
+        &mut self.inner
+    }
+
+    /// Consumes the wrapper and returns the backing configuration space.
+    pub fn into_inner(self) -> T {
+        self.inner
+    }
+}
+
+impl<T: PciConfig> PciConfig for NotifyConfig<T> {
+    fn read_register(&self, reg_idx: usize) -> Result<u32> {
+        self.inner.read_register(reg_idx)
+    }
+
+    fn write_register(&mut self, reg_idx: usize, value: u32) -> Result<()> {
+        let callbacks = match self.callbacks.get_mut(&reg_idx) {
+            Some(callbacks) => callbacks,
+            None => return self.inner.write_register(reg_idx, value),
+        };
+        let old = self.inner.read_register(reg_idx)?;
+        self.inner.write_register(reg_idx, value)?;
+        let new = self.inner.read_register(reg_idx)?;
+        for callback in callbacks.iter_mut() {
+            callback(old, new);
+        }
+        Ok(())
+    }
+
+    fn size(&self) -> usize {
+        self.inner.size()
+    }
+
+    fn register_access_policy(&self, reg_idx: usize) -> AccessWidth {
+        self.inner.register_access_policy(reg_idx)
+    }
+
+    // The reset is done by the device, so it doesn't call the callbacks.
+    fn apply_reset(&mut self, kind: ResetKind, policy: &ResetPolicy) -> Result<()> {
+        self.inner.apply_reset(kind, policy)
+    }
+}
@@ -0,0 +1,188 @@
+//! One-shot parsing of configuration space images.
+//!
+//! [`parse_config_space`](fn.parse_config_space.html) decodes a raw byte
+//! image of a configuration space, as dumped from a device or written by a
+//! guest. Malformed images are reported as errors; parsing never panics,
+//! whatever the input.
+
+use std::convert::TryFrom;
+use std::fmt;
+
+use crate::bar::{self, BarEncoding, BarMemoryType, BAR_IO_ADDRESS_MASK, BAR_MEM_ADDRESS_MASK};
+use crate::capability::ParsedCapability;
+use crate::extended_capability::ExtendedCapabilityRegion;
+use crate::pci_config::{
+    self, PciConfig, PciHeaderType, CLASS_CODE_OFFSET, COMMAND_OFFSET, DEVICE_ID_OFFSET,
+    HEADER_TYPE_MULTIFUNCTION, HEADER_TYPE_OFFSET, PROG_IF_OFFSET, REVISION_ID_OFFSET,
+    STATUS_OFFSET, SUBCLASS_OFFSET, VENDOR_ID_OFFSET,
+};
+use crate::{bridge, device};
+
+/// Errors returned when parsing a configuration space image.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Error {
+    /// Accessing the image failed, or its capability list is malformed.
+    ConfigAccess(pci_config::Error),
+    /// The BARs of the image are malformed.
+    Bar(bar::Error),
+    /// The Header Type register holds an unknown layout.
+    HeaderType(u8),
+}
+
+impl From<pci_config::Error> for Error {
+    fn from(e: pci_config::Error) -> Self {
+        Error::ConfigAccess(e)
+    }
+}
+
+impl From<bar::Error> for Error {
+    fn from(e: bar::Error) -> Self {
+        Error::Bar(e)
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::ConfigAccess(e) => write!(f, "configuration space access failed: {}", e),
+            Error::Bar(e) => write!(f, "invalid BAR: {}", e),
+            Error::HeaderType(layout) => write!(f, "unknown header layout {:#x}", layout),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+/// Result of the parsing operations.
+pub type Result<T> = std::result::Result<T, Error>;
+
+/// BAR decoded from a configuration space image.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ParsedBar {
+    /// Index of the BAR, or of the first of the pair for 64-bit BARs.
+    pub index: usize,
+    /// Low-order encoding bits of the BAR.
+    pub encoding: BarEncoding,
+    /// Base address programmed in the BAR. The size of the region can't be
+    /// known from an image, as it takes probing the BAR.
+    pub addr: u64,
+}
+
+/// Configuration space decoded from an image.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ParsedDevice {
+    /// Vendor ID register.
+    pub vendor_id: u16,
+    /// Device ID register.
+    pub device_id: u16,
+    /// Command register.
+    pub command: u16,
+    /// Status register.
+    pub status: u16,
+    /// Revision ID register.
+    pub revision_id: u8,
+    /// Programming Interface register.
+    pub prog_if: u8,
+    /// Subclass register.
+    pub subclass: u8,
+    /// Class Code register.
+    pub class_code: u8,
+    /// Layout of the header.
+    pub header_type: PciHeaderType,
+    /// Whether the multi-function bit of the Header Type register is set.
+    pub multifunction: bool,
+    /// BARs of the header, by increasing index. Cardbus bridges have none.
+    pub bars: Vec<ParsedBar>,
+    /// Capabilities, in list order.
+    pub capabilities: Vec<ParsedCapability>,
+    /// Extended capabilities, in list order.
+    pub extended_capabilities: Vec<ExtendedCapabilityRegion>,
+}
+
+// Read-only configuration space backed by an image.
+struct Image<'a>(&'a [u8]);
+
+impl PciConfig for Image<'_> {
+    fn read_register(&self, reg_idx: usize) -> pci_config::Result<u32> {
+        let offset = reg_idx.saturating_mul(4);
+        match self.0.get(offset..).and_then(|bytes| bytes.get(..4)) {
+            Some(bytes) => Ok(u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]])),
+            None => Err(pci_config::Error::OffsetOutOfBounds(offset)),
+        }
+    }
+
+    // The image is never written to.
+    fn write_register(&mut self, _reg_idx: usize, _value: u32) -> pci_config::Result<()> {
+        Ok(())
+    }
+
+    fn size(&self) -> usize {
+        self.0.len() & !0x3
+    }
+}
+
+/// Decodes the configuration space image `bytes`.
+///
+/// The image starts at offset 0 of the configuration space. It covers at
+/// least the header, and usually the whole standard or extended
+/// configuration space; trailing bytes that don't make a full register are
+/// ignored.
+pub fn parse_config_space(bytes: &[u8]) -> Result<ParsedDevice> {
+    let image = Image(bytes);
+    let header = image.read_byte(HEADER_TYPE_OFFSET)?;
+    let header_type = PciHeaderType::try_from(header).map_err(Error::HeaderType)?;
+    let (bars_offset, num_bars) = match header_type {
+        PciHeaderType::Device => (device::BARS_START_OFFSET, device::NUM_BARS),
+        PciHeaderType::PciToPciBridge => (bridge::BARS_START_OFFSET, bridge::NUM_BARS),
+        PciHeaderType::PciToCardbusBridge => (0, 0),
+    };
+
+    Ok(ParsedDevice {
+        vendor_id: image.read_word(VENDOR_ID_OFFSET)?,
+        device_id: image.read_word(DEVICE_ID_OFFSET)?,
+        command: image.read_word(COMMAND_OFFSET)?,
+        status: image.read_word(STATUS_OFFSET)?,
+        revision_id: image.read_byte(REVISION_ID_OFFSET)?,
+        prog_if: image.read_byte(PROG_IF_OFFSET)?,
+        subclass: image.read_byte(SUBCLASS_OFFSET)?,
+        class_code: image.read_byte(CLASS_CODE_OFFSET)?,
+        header_type,
+        multifunction: header & HEADER_TYPE_MULTIFUNCTION != 0,
+        bars: parse_bars(&image, bars_offset, num_bars)?,
+        capabilities: image.parse_capabilities()?,
+        extended_capabilities: image.extended_capabilities()?,
+    })
+}
+
+fn parse_bars(image: &Image, bars_offset: usize, num_bars: usize) -> Result<Vec<ParsedBar>> {
+    let mut bars = Vec::with_capacity(num_bars);
+    let mut index = 0;
+    while index < num_bars {
+        let low = image.read_register(bars_offset / 4 + index)?;
+        let encoding = BarEncoding::from_bits(low as u8);
+        let (addr, num_regs) = match encoding {
+            BarEncoding::Io => (u64::from(low & BAR_IO_ADDRESS_MASK), 1),
+            BarEncoding::Memory {
+                mem_type: BarMemoryType::Bits64,
+                ..
+            } => {
+                if index + 1 >= num_bars {
+                    return Err(Error::Bar(bar::Error::BarInvalid64(index)));
+                }
+                let high = image.read_register(bars_offset / 4 + index + 1)?;
+                (
+                    u64::from(high) << 32 | u64::from(low & BAR_MEM_ADDRESS_MASK),
+                    2,
+                )
+            }
+            BarEncoding::Memory { .. } => (u64::from(low & BAR_MEM_ADDRESS_MASK), 1),
+        };
+        bars.push(ParsedBar {
+            index,
+            encoding,
+            addr,
+        });
+        index += num_regs;
+    }
+    Ok(bars)
+}
@@ -0,0 +1,839 @@
+//! Generic access to a PCI configuration space.
+//!
+//! Implementors only provide 32-bit register accesses; the narrower accesses
+//! are derived from them with a read-modify-write sequence.
+
+use std::collections::BTreeMap;
+use std::convert::TryFrom;
+use std::fmt;
+
+use crate::capability::{self, CapabilityRegion, ParsedCapability, PciCapabilityId};
+use crate::extended_capability::{self, ExtendedCapabilityRegion};
+use crate::interrupt::{self, InterruptMode};
+use crate::layout;
+use crate::reset::{self, ResetKind, ResetPolicy};
+
+/// Size of the configuration space of a PCI function.
+pub const PCI_CONFIG_SPACE_SIZE: usize = 256;
+/// Size of the configuration space of a PCI Express function.
+pub const PCIE_CONFIG_SPACE_SIZE: usize = 4096;
+
+/// Offset of the Vendor ID.
+pub const VENDOR_ID_OFFSET: usize = 0x00;
+/// Offset of the Device ID.
+pub const DEVICE_ID_OFFSET: usize = 0x02;
+/// Offset of the Command register.
+pub const COMMAND_OFFSET: usize = 0x04;
+/// Offset of the Status register.
+pub const STATUS_OFFSET: usize = 0x06;
+/// Offset of the Revision ID.
+pub const REVISION_ID_OFFSET: usize = 0x08;
+/// Offset of the Programming Interface.
+pub const PROG_IF_OFFSET: usize = 0x09;
+/// Offset of the Subclass code.
+pub const SUBCLASS_OFFSET: usize = 0x0A;
+/// Offset of the Class code.
+pub const CLASS_CODE_OFFSET: usize = 0x0B;
+/// Offset of the Cache Line Size register.
+pub const CACHE_LINE_SIZE_OFFSET: usize = 0x0C;
+/// Offset of the Latency Timer.
+pub const LATENCY_TIMER_OFFSET: usize = 0x0D;
+/// Offset of the Header Type.
+pub const HEADER_TYPE_OFFSET: usize = 0x0E;
+/// Offset of the BIST register.
+pub const BIST_OFFSET: usize = 0x0F;
+
+/// Vendor ID read from a function that doesn't exist.
+pub const VENDOR_ID_ABSENT: u16 = 0xFFFF;
+
+/// Header Type bit set when the device implements multiple functions.
+pub const HEADER_TYPE_MULTIFUNCTION: u8 = 0x80;
+
+/// Command register bit enabling the decoding of I/O space BARs.
+pub const COMMAND_IO_SPACE: u16 = 1 << 0;
+/// Command register bit enabling the decoding of memory space BARs.
+pub const COMMAND_MEMORY_SPACE: u16 = 1 << 1;
+/// Command register bit disabling the assertion of INTx interrupts.
+pub const COMMAND_INTERRUPT_DISABLE: u16 = 1 << 10;
+
+/// Status register bit reflecting the state of the INTx interrupt of the
+/// function, whether or not the Interrupt Disable bit is set.
+pub const STATUS_INTERRUPT: u16 = 1 << 3;
+/// Status register bit set when the function implements a capability list.
+pub const STATUS_CAPABILITIES_LIST: u16 = 1 << 4;
+/// Status register error bits, set by the function and cleared by writing 1:
+/// Master Data Parity Error, Signaled and Received Target Abort, Received
+/// Master Abort, Signaled System Error and Detected Parity Error.
+pub const STATUS_WRITE_1_TO_CLEAR: u16 = 0xF900;
+
+/// Command register.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Command(pub u16);
+
+impl Command {
+    /// Returns whether the decoding of I/O space BARs is enabled.
+    pub fn io_space(self) -> bool {
+        self.0 & COMMAND_IO_SPACE != 0
+    }
+
+    /// Returns whether the decoding of memory space BARs is enabled.
+    pub fn memory_space(self) -> bool {
+        self.0 & COMMAND_MEMORY_SPACE != 0
+    }
+
+    /// Returns whether the assertion of INTx interrupts is disabled.
+    pub fn interrupt_disabled(self) -> bool {
+        self.0 & COMMAND_INTERRUPT_DISABLE != 0
+    }
+}
+
+/// Status register.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Status(pub u16);
+
+impl Status {
+    /// Returns whether the INTx interrupt of the function is asserted.
+    pub fn interrupt(self) -> bool {
+        self.0 & STATUS_INTERRUPT != 0
+    }
+
+    /// Returns whether the function implements a capability list.
+    pub fn capabilities_list(self) -> bool {
+        self.0 & STATUS_CAPABILITIES_LIST != 0
+    }
+
+    /// Returns the error bits, which are cleared by writing 1.
+    pub fn errors(self) -> u16 {
+        self.0 & STATUS_WRITE_1_TO_CLEAR
+    }
+}
+
+/// Errors returned by configuration space accesses.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Error {
+    /// The offset is not naturally aligned for the access width.
+    UnalignedAccess(usize),
+    /// The offset lies outside of the configuration space.
+    OffsetOutOfBounds(usize),
+    /// The register at the offset doesn't accept accesses of that width.
+    UnsupportedAccessWidth(usize),
+    /// The capability list is malformed at the offset: a next pointer loops
+    /// back, an extended capability next pointer points before the start of
+    /// the list, or a capability doesn't fit in the standard configuration
+    /// space.
+    CapabilityListCorrupt(usize),
+    /// The secondary bus number of a bridge, the first value, is larger than
+    /// its subordinate bus number, the second value.
+    BusRangeInvalid(u8, u8),
+    /// The capabilities to install exceed their part of the configuration
+    /// space by this number of bytes.
+    CapabilitiesOverflow(usize),
+    /// The function isn't the expected one. The first value holds the
+    /// expected Vendor ID and Device ID, the second one the IDs read.
+    IdentityMismatch((u16, u16), (u16, u16)),
+    /// The buffer can't hold the capability list, which has this number of
+    /// capabilities.
+    CapabilityBufferTooSmall(usize),
+    /// The capability at the offset doesn't have the expected ID.
+    CapabilityIdMismatch(usize),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::UnalignedAccess(offset) => write!(f, "unaligned access at offset {:#x}", offset),
+            Error::OffsetOutOfBounds(offset) => {
+                write!(f, "offset {:#x} is out of bounds", offset)
+            }
+            Error::UnsupportedAccessWidth(offset) => {
+                write!(f, "unsupported access width at offset {:#x}", offset)
+            }
+            Error::CapabilityListCorrupt(offset) => {
+                write!(f, "corrupt capability list at offset {:#x}", offset)
+            }
+            Error::BusRangeInvalid(secondary, subordinate) => write!(
+                f,
+                "secondary bus {:#x} is above subordinate bus {:#x}",
+                secondary, subordinate
+            ),
+            Error::CapabilitiesOverflow(excess) => write!(
+                f,
+                "capabilities exceed the configuration space by {} bytes",
+                excess
+            ),
+            Error::IdentityMismatch((vendor, device), (found_vendor, found_device)) => write!(
+                f,
+                "expected function {:04x}:{:04x}, found {:04x}:{:04x}",
+                vendor, device, found_vendor, found_device
+            ),
+            Error::CapabilityBufferTooSmall(count) => {
+                write!(f, "buffer too small for {} capabilities", count)
+            }
+            Error::CapabilityIdMismatch(offset) => {
+                write!(f, "unexpected capability ID at offset {:#x}", offset)
+            }
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+/// Result type for configuration space accesses.
+pub type Result<T> = std::result::Result<T, Error>;
+
+/// Layout of the configuration header, from the Header Type register.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum PciHeaderType {
+    /// Type 0 header of endpoint devices.
+    Device,
+    /// Type 1 header of PCI-to-PCI bridges.
+    PciToPciBridge,
+    /// Type 2 header of PCI-to-CardBus bridges.
+    PciToCardbusBridge,
+}
+
+impl TryFrom<u8> for PciHeaderType {
+    type Error = u8;
+
+    /// Decodes a Header Type register, ignoring the multi-function bit.
+    /// Returns the layout bits if they don't match a known header type.
+    fn try_from(value: u8) -> std::result::Result<Self, u8> {
+        match value & !HEADER_TYPE_MULTIFUNCTION {
+            0x00 => Ok(PciHeaderType::Device),
+            0x01 => Ok(PciHeaderType::PciToPciBridge),
+            0x02 => Ok(PciHeaderType::PciToCardbusBridge),
+            layout => Err(layout),
+        }
+    }
+}
+
+impl From<PciHeaderType> for u8 {
+    fn from(header_type: PciHeaderType) -> Self {
+        match header_type {
+            PciHeaderType::Device => 0x00,
+            PciHeaderType::PciToPciBridge => 0x01,
+            PciHeaderType::PciToCardbusBridge => 0x02,
+        }
+    }
+}
+
+/// Fields of the first 16 bytes of the configuration header, common to all
+/// header types.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct CommonHeader {
+    /// Vendor ID register.
+    pub vendor_id: u16,
+    /// Device ID register.
+    pub device_id: u16,
+    /// Command register.
+    pub command: u16,
+    /// Status register.
+    pub status: u16,
+    /// Revision ID register.
+    pub revision_id: u8,
+    /// Programming Interface register.
+    pub prog_if: u8,
+    /// Subclass register.
+    pub subclass: u8,
+    /// Class Code register.
+    pub class_code: u8,
+    /// Cache Line Size register.
+    pub cache_line_size: u8,
+    /// Latency Timer.
+    pub latency_timer: u8,
+    /// Header Type register, multi-function bit included.
+    pub header_type: u8,
+    /// BIST register.
+    pub bist: u8,
+}
+
+impl CommonHeader {
+    /// Returns the layout of the rest of the header, or the layout bits of
+    /// the Header Type register if they don't match a known header type.
+    pub fn layout(&self) -> std::result::Result<PciHeaderType, u8> {
+        PciHeaderType::try_from(self.header_type)
+    }
+
+    /// Returns whether the multi-function bit of the Header Type register is
+    /// set.
+    pub fn is_multifunction(&self) -> bool {
+        self.header_type & HEADER_TYPE_MULTIFUNCTION != 0
+    }
+}
+
+/// Reads the common header of `cfg`, or returns `None` if the Vendor ID reads
+/// as `VENDOR_ID_ABSENT`, meaning that there is no function there.
+///
+/// This is the first step of enumeration. Only the common header is read, so
+/// this works whatever the header type.
+pub fn probe<C: PciConfig + ?Sized>(cfg: &C) -> Result<Option<CommonHeader>> {
+    let vendor_id = cfg.read_word(VENDOR_ID_OFFSET)?;
+    if vendor_id == VENDOR_ID_ABSENT {
+        return Ok(None);
+    }
+    Ok(Some(CommonHeader {
+        vendor_id,
+        device_id: cfg.read_word(DEVICE_ID_OFFSET)?,
+        command: cfg.read_word(COMMAND_OFFSET)?,
+        status: cfg.read_word(STATUS_OFFSET)?,
+        revision_id: cfg.read_byte(REVISION_ID_OFFSET)?,
+        prog_if: cfg.read_byte(PROG_IF_OFFSET)?,
+        subclass: cfg.read_byte(SUBCLASS_OFFSET)?,
+        class_code: cfg.read_byte(CLASS_CODE_OFFSET)?,
+        cache_line_size: cfg.read_byte(CACHE_LINE_SIZE_OFFSET)?,
+        latency_timer: cfg.read_byte(LATENCY_TIMER_OFFSET)?,
+        header_type: cfg.read_byte(HEADER_TYPE_OFFSET)?,
+        bist: cfg.read_byte(BIST_OFFSET)?,
+    }))
+}
+
+/// Base class of a function, from the Class Code register.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum PciClassCode {
+    /// Device built before class codes were defined.
+    TooOld,
+    /// Mass storage controller.
+    MassStorage,
+    /// Network controller.
+    NetworkController,
+    /// Display controller.
+    DisplayController,
+    /// Multimedia controller.
+    MultimediaController,
+    /// Memory controller.
+    MemoryController,
+    /// Bridge device.
+    BridgeDevice,
+    /// Simple communication controller.
+    SimpleCommunicationController,
+    /// Base system peripheral.
+    BaseSystemPeripheral,
+    /// Input device controller.
+    InputDevice,
+    /// Docking station.
+    DockingStation,
+    /// Processor.
+    Processor,
+    /// Serial bus controller.
+    SerialBusController,
+    /// Wireless controller.
+    WirelessController,
+    /// Intelligent I/O controller.
+    IntelligentIoController,
+    /// Satellite communication controller.
+    SatelliteCommunicationController,
+    /// Encryption controller.
+    EncryptionController,
+    /// Data acquisition and signal processing controller.
+    DataAcquisitionSignalProcessing,
+    /// Processing accelerator.
+    ProcessingAccelerator,
+    /// Non-essential instrumentation.
+    NonEssentialInstrumentation,
+    /// Device that doesn't fit any defined class.
+    Other,
+    /// Class not defined by the specification.
+    Unknown(u8),
+}
+
+impl From<u8> for PciClassCode {
+    fn from(class: u8) -> Self {
+        match class {
+            0x00 => PciClassCode::TooOld,
+            0x01 => PciClassCode::MassStorage,
+            0x02 => PciClassCode::NetworkController,
+            0x03 => PciClassCode::DisplayController,
+            0x04 => PciClassCode::MultimediaController,
+            0x05 => PciClassCode::MemoryController,
+            0x06 => PciClassCode::BridgeDevice,
+            0x07 => PciClassCode::SimpleCommunicationController,
+            0x08 => PciClassCode::BaseSystemPeripheral,
+            0x09 => PciClassCode::InputDevice,
+            0x0A => PciClassCode::DockingStation,
+            0x0B => PciClassCode::Processor,
+            0x0C => PciClassCode::SerialBusController,
+            0x0D => PciClassCode::WirelessController,
+            0x0E => PciClassCode::IntelligentIoController,
+            0x0F => PciClassCode::SatelliteCommunicationController,
+            0x10 => PciClassCode::EncryptionController,
+            0x11 => PciClassCode::DataAcquisitionSignalProcessing,
+            0x12 => PciClassCode::ProcessingAccelerator,
+            0x13 => PciClassCode::NonEssentialInstrumentation,
+            0xFF => PciClassCode::Other,
+            class => PciClassCode::Unknown(class),
+        }
+    }
+}
+
+impl From<PciClassCode> for u8 {
+    fn from(class: PciClassCode) -> Self {
+        match class {
+            PciClassCode::TooOld => 0x00,
+            PciClassCode::MassStorage => 0x01,
+            PciClassCode::NetworkController => 0x02,
+            PciClassCode::DisplayController => 0x03,
+            PciClassCode::MultimediaController => 0x04,
+            PciClassCode::MemoryController => 0x05,
+            PciClassCode::BridgeDevice => 0x06,
+            PciClassCode::SimpleCommunicationController => 0x07,
+            PciClassCode::BaseSystemPeripheral => 0x08,
+            PciClassCode::InputDevice => 0x09,
+            PciClassCode::DockingStation => 0x0A,
+            PciClassCode::Processor => 0x0B,
+            PciClassCode::SerialBusController => 0x0C,
+            PciClassCode::WirelessController => 0x0D,
+            PciClassCode::IntelligentIoController => 0x0E,
+            PciClassCode::SatelliteCommunicationController => 0x0F,
+            PciClassCode::EncryptionController => 0x10,
+            PciClassCode::DataAcquisitionSignalProcessing => 0x11,
+            PciClassCode::ProcessingAccelerator => 0x12,
+            PciClassCode::NonEssentialInstrumentation => 0x13,
+            PciClassCode::Other => 0xFF,
+            PciClassCode::Unknown(class) => class,
+        }
+    }
+}
+
+/// Width of a configuration space access.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum AccessWidth {
+    /// 8-bit access.
+    Byte,
+    /// 16-bit access.
+    Word,
+    /// 32-bit access.
+    Dword,
+}
+
+/// Access to a PCI configuration space made of 32-bit registers.
+pub trait PciConfig {
+    /// Reads the register at index `reg_idx`.
+    fn read_register(&self, reg_idx: usize) -> Result<u32>;
+
+    /// Writes `value` to the register at index `reg_idx`.
+    fn write_register(&mut self, reg_idx: usize, value: u32) -> Result<()>;
+
+    /// Returns the size of the configuration space in bytes.
+    ///
+    /// The accessors of the trait reject offsets past this size before
+    /// calling `read_register` or `write_register`. Defaults to the size of
+    /// the standard configuration space.
+    fn size(&self) -> usize {
+        PCI_CONFIG_SPACE_SIZE
+    }
+
+    /// Returns the narrowest access width the register at `reg_idx` accepts.
+    ///
+    /// The word and byte accessors fail with `UnsupportedAccessWidth` for
+    /// registers that require wider accesses. By default every register
+    /// accepts byte accesses.
+    fn register_access_policy(&self, _reg_idx: usize) -> AccessWidth {
+        AccessWidth::Byte
+    }
+
+    /// Reads the 16-bit word at byte `offset`.
+    fn read_word(&self, offset: usize) -> Result<u16> {
+        if offset & 0x1 != 0 {
+            return Err(Error::UnalignedAccess(offset));
+        }
+        check_bounds(self, offset, 2)?;
+        check_access_width(self, offset, AccessWidth::Word)?;
+        let shift = (offset % 4) * 8;
+        Ok((self.read_register(offset / 4)? >> shift) as u16)
+    }
+
+    /// Reads the byte at `offset`.
+    fn read_byte(&self, offset: usize) -> Result<u8> {
+        check_bounds(self, offset, 1)?;
+        check_access_width(self, offset, AccessWidth::Byte)?;
+        let shift = (offset % 4) * 8;
+        Ok((self.read_register(offset / 4)? >> shift) as u8)
+    }
+
+    /// Writes the 16-bit word at byte `offset`.
+    ///
+    /// This reads the whole register, modifies it and writes it back. Shared
+    /// configuration spaces serialize the accesses with
+    /// [`SyncConfig`](../sync/struct.SyncConfig.html) so that concurrent
+    /// updates to the same register aren't lost. The `STATUS_WRITE_1_TO_CLEAR`
+    /// bits are written back as 0 when the access doesn't cover them, so that
+    /// writing the Command register doesn't clear them.
+    fn write_word(&mut self, offset: usize, value: u16) -> Result<()> {
+        if offset & 0x1 != 0 {
+            return Err(Error::UnalignedAccess(offset));
+        }
+        check_bounds(self, offset, 2)?;
+        check_access_width(self, offset, AccessWidth::Word)?;
+        let shift = (offset % 4) * 8;
+        let reg = read_for_update(self, offset / 4, 0xffff << shift)?;
+        let reg = (reg & !(0xffff << shift)) | (u32::from(value) << shift);
+        self.write_register(offset / 4, reg)
+    }
+
+    /// Writes the byte at `offset`, like [`write_word`](#method.write_word).
+    fn write_byte(&mut self, offset: usize, value: u8) -> Result<()> {
+        check_bounds(self, offset, 1)?;
+        check_access_width(self, offset, AccessWidth::Byte)?;
+        let shift = (offset % 4) * 8;
+        let reg = read_for_update(self, offset / 4, 0xff << shift)?;
+        let reg = (reg & !(0xff << shift)) | (u32::from(value) << shift);
+        self.write_register(offset / 4, reg)
+    }
+
+    /// Reads `data.len()` bytes at `offset`, as a bus access of that width.
+    ///
+    /// This is the entry point for PIO and MMIO accesses to the configuration
+    /// space, once the bus address has been translated to an offset. The
+    /// access must be 1, 2 or 4 bytes wide and naturally aligned. The data is
+    /// little-endian.
+    fn read_data(&self, offset: usize, data: &mut [u8]) -> Result<()> {
+        match data.len() {
+            1 => data[0] = self.read_byte(offset)?,
+            2 => data.copy_from_slice(&self.read_word(offset)?.to_le_bytes()),
+            4 => {
+                if offset & 0x3 != 0 {
+                    return Err(Error::UnalignedAccess(offset));
+                }
+                check_bounds(self, offset, 4)?;
+                data.copy_from_slice(&self.read_register(offset / 4)?.to_le_bytes());
+            }
+            _ => return Err(Error::UnsupportedAccessWidth(offset)),
+        }
+        Ok(())
+    }
+
+    /// Writes `data` at `offset`, as a bus access of `data.len()` bytes.
+    ///
+    /// See [`read_data`](#method.read_data) for the accepted accesses.
+    fn write_data(&mut self, offset: usize, data: &[u8]) -> Result<()> {
+        match *data {
+            [value] => self.write_byte(offset, value),
+            [b0, b1] => self.write_word(offset, u16::from_le_bytes([b0, b1])),
+            [b0, b1, b2, b3] => {
+                if offset & 0x3 != 0 {
+                    return Err(Error::UnalignedAccess(offset));
+                }
+                check_bounds(self, offset, 4)?;
+                self.write_register(offset / 4, u32::from_le_bytes([b0, b1, b2, b3]))
+            }
+            _ => Err(Error::UnsupportedAccessWidth(offset)),
+        }
+    }
+
+    /// Checks that the Vendor ID and Device ID are `expected_vendor` and
+    /// `expected_device`, or fails with `IdentityMismatch`.
+    ///
+    /// This guards against restoring the state of a function into the model
+    /// of another one.
+    fn verify_identity(&self, expected_vendor: u16, expected_device: u16) -> Result<()> {
+        let identity = self.read_register(VENDOR_ID_OFFSET / 4)?;
+        let found = (identity as u16, (identity >> 16) as u16);
+        if found != (expected_vendor, expected_device) {
+            return Err(Error::IdentityMismatch(
+                (expected_vendor, expected_device),
+                found,
+            ));
+        }
+        Ok(())
+    }
+
+    /// Returns the Class Code register, the base class of the function.
+    fn class_code(&self) -> Result<u8> {
+        self.read_byte(CLASS_CODE_OFFSET)
+    }
+
+    /// Returns the base class of the function.
+    fn class(&self) -> Result<PciClassCode> {
+        self.class_code().map(PciClassCode::from)
+    }
+
+    /// Returns whether the function is a bridge, of any kind.
+    ///
+    /// This checks the class of the function, which also covers host and
+    /// ISA bridges, not its header type.
+    fn is_bridge(&self) -> Result<bool> {
+        Ok(self.class()? == PciClassCode::BridgeDevice)
+    }
+
+    /// Returns the Cache Line Size register.
+    fn cache_line_size(&self) -> Result<u8> {
+        self.read_byte(CACHE_LINE_SIZE_OFFSET)
+    }
+
+    /// Sets the Cache Line Size register.
+    fn write_cache_line_size(&mut self, value: u8) -> Result<()> {
+        self.write_byte(CACHE_LINE_SIZE_OFFSET, value)
+    }
+
+    /// Returns the Latency Timer.
+    fn latency_timer(&self) -> Result<u8> {
+        self.read_byte(LATENCY_TIMER_OFFSET)
+    }
+
+    /// Sets the Latency Timer.
+    fn write_latency_timer(&mut self, value: u8) -> Result<()> {
+        self.write_byte(LATENCY_TIMER_OFFSET, value)
+    }
+
+    /// Returns the Command and Status registers, read at once as they share
+    /// a register.
+    fn command_status(&self) -> Result<(Command, Status)> {
+        let value = self.read_register(COMMAND_OFFSET / 4)?;
+        Ok((Command(value as u16), Status((value >> 16) as u16)))
+    }
+
+    /// Sets the Command register to `command` and clears the Status error
+    /// bits set in `status_clear`, with a single register write.
+    ///
+    /// The Status half of the write holds the error bits of `status_clear`
+    /// and 0 elsewhere, as the error bits are cleared by writing 1. The other
+    /// Status bits aren't writable by the guest. Passing back the Status
+    /// returned by `command_status` clears the errors it reported.
+    fn write_command_status(&mut self, command: Command, status_clear: Status) -> Result<()> {
+        self.write_register(
+            COMMAND_OFFSET / 4,
+            u32::from(command.0) | (u32::from(status_clear.errors()) << 16),
+        )
+    }
+
+    /// Sets the Capabilities List bit of the Status register.
+    ///
+    /// Guests only walk the capability list when the bit is set.
+    /// [`ConfigSpace::install_capabilities`](../config_space/struct.ConfigSpace.html#method.install_capabilities)
+    /// manages the bit already; this is for configuration spaces set up by
+    /// other means. The default implementation writes the Status register,
+    /// implementations where the bit is read-only to the guest override it.
+    fn set_capabilities_list_bit(&mut self) -> Result<()> {
+        let status = self.read_word(STATUS_OFFSET)? & !STATUS_WRITE_1_TO_CLEAR;
+        self.write_word(STATUS_OFFSET, status | STATUS_CAPABILITIES_LIST)
+    }
+
+    /// Clears the Capabilities List bit of the Status register.
+    fn clear_capabilities_list_bit(&mut self) -> Result<()> {
+        let status = self.read_word(STATUS_OFFSET)? & !STATUS_WRITE_1_TO_CLEAR;
+        self.write_word(STATUS_OFFSET, status & !STATUS_CAPABILITIES_LIST)
+    }
+
+    /// Walks the capability list and returns the location of every
+    /// capability, in list order.
+    ///
+    /// The list is empty if the Capabilities List bit of the Status register
+    /// is clear.
+    fn capabilities(&self) -> Result<Vec<CapabilityRegion>> {
+        capability::walk(self)
+    }
+
+    /// Walks the capability list like [`capabilities`](#method.capabilities),
+    /// storing the location of every capability in `buf` rather than
+    /// allocating, and returns the number of capabilities.
+    ///
+    /// Fails with `CapabilityBufferTooSmall` if `buf` can't hold them all; a
+    /// buffer of `MAX_CAPABILITIES` entries always can.
+    fn capabilities_into(&self, buf: &mut [CapabilityRegion]) -> Result<usize> {
+        capability::walk_into(self, buf)
+    }
+
+    /// Returns the `(offset, length)` byte ranges of the standard
+    /// configuration space, past the header, that no capability uses, by
+    /// increasing offset.
+    ///
+    /// The capabilities the crate doesn't decode are taken to extend up to
+    /// the next capability, so they leave no gap behind them.
+    fn capability_gaps(&self) -> Result<Vec<(usize, usize)>> {
+        capability::gaps(self)
+    }
+
+    /// Walks the capability list and decodes every capability, in list
+    /// order.
+    fn parse_capabilities(&self) -> Result<Vec<ParsedCapability>> {
+        capability::parse(self)
+    }
+
+    /// Returns the `len` bytes at `offset`, typically the bytes of a
+    /// capability to decode.
+    ///
+    /// Fails with `OffsetOutOfBounds` if the bytes extend past the end of the
+    /// configuration space.
+    fn capability_bytes(&self, offset: usize, len: usize) -> Result<Vec<u8>> {
+        match offset.checked_add(len) {
+            Some(end) if end <= self.size() => (offset..end).map(|o| self.read_byte(o)).collect(),
+            _ => Err(Error::OffsetOutOfBounds(offset)),
+        }
+    }
+
+    /// Returns a readable summary of the capability list, one line per
+    /// capability in list order.
+    ///
+    /// Each line holds the offset and ID of the capability and, for the
+    /// capabilities the crate decodes, their main fields. This is meant for
+    /// logs and debugging; the format isn't stable.
+    fn capability_report(&self) -> Result<String> {
+        capability::report(self)
+    }
+
+    /// Returns the `(name, offset, value)` of every field of the `header`
+    /// layout, by increasing offset, with the values read from the
+    /// configuration space.
+    ///
+    /// This is the labeled dump of the header debugging tools print. See
+    /// [`layout::fields`](../layout/fn.fields.html) for the fields listed.
+    fn fields(&self, header: PciHeaderType) -> Result<Vec<(&'static str, usize, u64)>> {
+        layout::read_fields(self, header)
+    }
+
+    /// Returns the mechanism the function currently signals its interrupts
+    /// with.
+    ///
+    /// This is to be checked again after guest writes to the Command register
+    /// or to the MSI and MSI-X capabilities.
+    fn active_interrupt_mode(&self) -> Result<InterruptMode> {
+        interrupt::active_mode(self)
+    }
+
+    /// Returns the ID of the capability at `offset`, without walking the
+    /// list.
+    ///
+    /// This is for re-reading a capability whose offset is already known.
+    /// Fails with `OffsetOutOfBounds` if `offset` is outside of the
+    /// configuration space.
+    fn capability_id_at(&self, offset: usize) -> Result<PciCapabilityId> {
+        Ok(PciCapabilityId::from(self.read_byte(offset)?))
+    }
+
+    /// Returns the offset of the first capability with the given `id`, or
+    /// `None` if the capability list doesn't contain one.
+    fn find_capability(&self, id: PciCapabilityId) -> Result<Option<usize>> {
+        capability::find(self, id)
+    }
+
+    /// Returns the offset of every capability, keyed by capability ID.
+    ///
+    /// When the list holds several capabilities with the same ID, as is
+    /// common for vendor specific ones, only the first one is kept.
+    fn capability_map(&self) -> Result<BTreeMap<PciCapabilityId, usize>> {
+        let mut map = BTreeMap::new();
+        for region in self.capabilities()? {
+            map.entry(region.id).or_insert(region.offset);
+        }
+        Ok(map)
+    }
+
+    /// Applies a Function Level Reset following `policy`, which tells the
+    /// bits to clear, to preserve and to reset to their default value.
+    ///
+    /// The reset is done by [`apply_reset`](#method.apply_reset).
+    fn flr_reset(&mut self, policy: &ResetPolicy) -> Result<()> {
+        self.apply_reset(ResetKind::FunctionLevel, policy)
+    }
+
+    /// Applies a reset of kind `kind` following `policy`.
+    ///
+    /// Sticky bits are preserved, except by a cold reset which clears them.
+    /// Volatile bits are cleared and hardware-initialized bits take their
+    /// default value.
+    ///
+    /// The default implementation writes the registers through
+    /// `write_register`, so bits the guest can't write keep their value.
+    /// Implementations holding device-managed state override it to reset
+    /// every bit.
+    fn apply_reset(&mut self, kind: ResetKind, policy: &ResetPolicy) -> Result<()> {
+        reset::reset(self, kind, policy)
+    }
+
+    /// Checks the registers against `template`, a list of `(register index,
+    /// expected value, mask)`: the value of each register, masked, must equal
+    /// the expected value.
+    ///
+    /// This is meant for the tests of device models, which can check the bits
+    /// they care about only.
+    ///
+    /// # Panics
+    ///
+    /// Panics if a register doesn't match its template, or can't be read.
+    fn assert_matches_template(&self, template: &[(usize, u32, u32)]) {
+        for &(reg_idx, expected, mask) in template {
+            let value = match self.read_register(reg_idx) {
+                Ok(value) => value,
+                Err(e) => panic!("failed to read register {:#x}: {}", reg_idx, e),
+            };
+            assert!(
+                value & mask == expected,
+                "register {:#x} is {:#010x}, expected {:#010x} with mask {:#010x}",
+                reg_idx,
+                value,
+                expected,
+                mask
+            );
+        }
+    }
+
+    /// Walks the extended capability list and returns the location and
+    /// version of every extended capability, in list order.
+    ///
+    /// The list is empty if the configuration space has no extended part or
+    /// no extended capability. Use
+    /// [`is_version_known`](../extended_capability/struct.ExtendedCapabilityRegion.html#method.is_version_known)
+    /// to spot capabilities newer than the crate knows.
+    fn extended_capabilities(&self) -> Result<Vec<ExtendedCapabilityRegion>> {
+        extended_capability::walk(self)
+    }
+
+    /// Reads the word at `field_offset` bytes into the capability at
+    /// `cap_offset`.
+    ///
+    /// The word must lie within the standard configuration space, where
+    /// capabilities live.
+    fn read_cap_word(&self, cap_offset: usize, field_offset: usize) -> Result<u16> {
+        self.read_word(cap_field_offset(cap_offset, field_offset)?)
+    }
+
+    /// Writes the word at `field_offset` bytes into the capability at
+    /// `cap_offset`.
+    ///
+    /// The word must lie within the standard configuration space, where
+    /// capabilities live.
+    fn write_cap_word(&mut self, cap_offset: usize, field_offset: usize, value: u16) -> Result<()> {
+        self.write_word(cap_field_offset(cap_offset, field_offset)?, value)
+    }
+}
+
+// Returns the offset of the word at `field_offset` into the capability at
+// `cap_offset`, checking that it fits in the standard configuration space.
+fn cap_field_offset(cap_offset: usize, field_offset: usize) -> Result<usize> {
+    match cap_offset.checked_add(field_offset) {
+        Some(offset) if offset <= PCI_CONFIG_SPACE_SIZE - 2 => Ok(offset),
+        Some(offset) => Err(Error::OffsetOutOfBounds(offset)),
+        None => Err(Error::OffsetOutOfBounds(cap_offset)),
+    }
+}
+
+// Reads the register at `reg_idx` before writing the `written` bits of it,
+// with the Status bits cleared by writing 1 zeroed outside of them.
+fn read_for_update<C: PciConfig + ?Sized>(cfg: &C, reg_idx: usize, written: u32) -> Result<u32> {
+    let mut value = cfg.read_register(reg_idx)?;
+    if reg_idx == STATUS_OFFSET / 4 {
+        let write_1_to_clear = u32::from(STATUS_WRITE_1_TO_CLEAR) << ((STATUS_OFFSET % 4) * 8);
+        value &= !(write_1_to_clear & !written);
+    }
+    Ok(value)
+}
+
+// Checks that an access of `len` bytes at `offset` is within the
+// configuration space.
+fn check_bounds<C: PciConfig + ?Sized>(cfg: &C, offset: usize, len: usize) -> Result<()> {
+    if offset >= cfg.size() || cfg.size() - offset < len {
+        return Err(Error::OffsetOutOfBounds(offset));
+    }
+    Ok(())
+}
+
+fn check_access_width<C: PciConfig + ?Sized>(
+    cfg: &C,
+    offset: usize,
+    width: AccessWidth,
+) -> Result<()> {
+    if cfg.register_access_policy(offset / 4) > width {
+        return Err(Error::UnsupportedAccessWidth(offset));
+    }
+    Ok(())
+}
@@ -0,0 +1,661 @@
+//! PCI Express capability.
+
+use std::fmt;
+
+use crate::capability::{
+    self, read_u16, read_u32, write_u16, write_u32, PciCapability, PciCapabilityId,
+};
+use crate::pci_config::{self, PciConfig};
+
+/// Offset of the PCI Express Capabilities register.
+pub const CAPABILITIES_OFFSET: usize = 0x02;
+/// Offset of the Device Capabilities register.
+pub const DEVICE_CAPABILITIES_OFFSET: usize = 0x04;
+/// Offset of the Device Control register.
+pub const DEVICE_CONTROL_OFFSET: usize = 0x08;
+/// Offset of the Device Status register.
+pub const DEVICE_STATUS_OFFSET: usize = 0x0A;
+/// Offset of the Link Capabilities register.
+pub const LINK_CAPABILITIES_OFFSET: usize = 0x0C;
+/// Offset of the Link Control register.
+pub const LINK_CONTROL_OFFSET: usize = 0x10;
+/// Offset of the Link Status register.
+pub const LINK_STATUS_OFFSET: usize = 0x12;
+/// Offset of the Slot Capabilities register.
+pub const SLOT_CAPABILITIES_OFFSET: usize = 0x14;
+/// Offset of the Slot Control register.
+pub const SLOT_CONTROL_OFFSET: usize = 0x18;
+/// Offset of the Slot Status register.
+pub const SLOT_STATUS_OFFSET: usize = 0x1A;
+/// Offset of the Root Control register.
+pub const ROOT_CONTROL_OFFSET: usize = 0x1C;
+/// Offset of the Root Capabilities register.
+pub const ROOT_CAPABILITIES_OFFSET: usize = 0x1E;
+/// Offset of the Root Status register.
+pub const ROOT_STATUS_OFFSET: usize = 0x20;
+/// Offset of the Device Capabilities 2 register.
+pub const DEVICE_CAPABILITIES_2_OFFSET: usize = 0x24;
+/// Offset of the Device Control 2 register.
+pub const DEVICE_CONTROL_2_OFFSET: usize = 0x28;
+/// Offset of the Device Status 2 register.
+pub const DEVICE_STATUS_2_OFFSET: usize = 0x2A;
+/// Offset of the Link Capabilities 2 register.
+pub const LINK_CAPABILITIES_2_OFFSET: usize = 0x2C;
+/// Offset of the Link Control 2 register.
+pub const LINK_CONTROL_2_OFFSET: usize = 0x30;
+/// Offset of the Link Status 2 register.
+pub const LINK_STATUS_2_OFFSET: usize = 0x32;
+/// Offset of the Slot Capabilities 2 register.
+pub const SLOT_CAPABILITIES_2_OFFSET: usize = 0x34;
+/// Offset of the Slot Control 2 register.
+pub const SLOT_CONTROL_2_OFFSET: usize = 0x38;
+/// Offset of the Slot Status 2 register.
+pub const SLOT_STATUS_2_OFFSET: usize = 0x3A;
+
+/// Link Capabilities and Link Status bits holding the link speed.
+pub const LINK_SPEED_MASK: u32 = 0xf;
+/// Shift of the link width in the Link Capabilities and Link Status
+/// registers.
+pub const LINK_WIDTH_SHIFT: u32 = 4;
+/// Link Capabilities and Link Status bits holding the link width, once
+/// shifted.
+pub const LINK_WIDTH_MASK: u32 = 0x3f;
+/// Shift of the Device/Port Type field in the PCI Express Capabilities
+/// register.
+pub const CAPABILITIES_PORT_TYPE_SHIFT: u32 = 4;
+/// PCI Express Capabilities bits holding the Device/Port Type, once shifted.
+pub const CAPABILITIES_PORT_TYPE_MASK: u16 = 0xf;
+/// Link Status bit set while the link is training.
+pub const LINK_STATUS_TRAINING: u16 = 1 << 11;
+/// Device Capabilities bits holding the Max Payload Size Supported.
+pub const DEVICE_CAPABILITIES_MPS_MASK: u32 = 0x7;
+/// Device Control bit enabling the reporting of correctable errors.
+pub const DEVICE_CONTROL_CORRECTABLE_ERROR: u16 = 1 << 0;
+/// Device Control bit enabling the reporting of non-fatal errors.
+pub const DEVICE_CONTROL_NON_FATAL_ERROR: u16 = 1 << 1;
+/// Device Control bit enabling the reporting of fatal errors.
+pub const DEVICE_CONTROL_FATAL_ERROR: u16 = 1 << 2;
+/// Device Control bit enabling the reporting of unsupported requests.
+pub const DEVICE_CONTROL_UNSUPPORTED_REQUEST: u16 = 1 << 3;
+/// Shift of the Max Payload Size in the Device Control register.
+pub const DEVICE_CONTROL_MPS_SHIFT: u32 = 5;
+/// Shift of the Max Read Request Size in the Device Control register.
+pub const DEVICE_CONTROL_MRRS_SHIFT: u32 = 12;
+/// Device Control bits holding the Max Payload Size and the Max Read Request
+/// Size, once shifted.
+pub const DEVICE_CONTROL_SIZE_MASK: u16 = 0x7;
+
+/// Errors related to the PCI Express capability.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Error {
+    /// The Max Payload Size is larger than the Max Payload Size Supported.
+    PayloadSizeUnsupported(PciePayloadSize),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::PayloadSizeUnsupported(size) => write!(
+                f,
+                "max payload size of {} bytes is not supported",
+                size.bytes()
+            ),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+/// Result of the PCI Express capability operations.
+pub type Result<T> = std::result::Result<T, Error>;
+
+/// Error reporting that can be enabled in the Device Control register.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PcieErrorReporting {
+    /// Correctable errors.
+    Correctable,
+    /// Non-fatal uncorrectable errors.
+    NonFatal,
+    /// Fatal uncorrectable errors.
+    Fatal,
+    /// Unsupported requests.
+    UnsupportedRequest,
+}
+
+impl PcieErrorReporting {
+    // Returns the enable bit of the Device Control register.
+    fn bit(self) -> u16 {
+        match self {
+            PcieErrorReporting::Correctable => DEVICE_CONTROL_CORRECTABLE_ERROR,
+            PcieErrorReporting::NonFatal => DEVICE_CONTROL_NON_FATAL_ERROR,
+            PcieErrorReporting::Fatal => DEVICE_CONTROL_FATAL_ERROR,
+            PcieErrorReporting::UnsupportedRequest => DEVICE_CONTROL_UNSUPPORTED_REQUEST,
+        }
+    }
+}
+
+/// Size of the payload or of the read requests of PCI Express transactions.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum PciePayloadSize {
+    /// 128 bytes.
+    B128,
+    /// 256 bytes.
+    B256,
+    /// 512 bytes.
+    B512,
+    /// 1024 bytes.
+    B1024,
+    /// 2048 bytes.
+    B2048,
+    /// 4096 bytes.
+    B4096,
+}
+
+impl PciePayloadSize {
+    /// Decodes a size field, returning `None` for reserved values.
+    pub fn from_bits(bits: u8) -> Option<Self> {
+        match bits {
+            0 => Some(PciePayloadSize::B128),
+            1 => Some(PciePayloadSize::B256),
+            2 => Some(PciePayloadSize::B512),
+            3 => Some(PciePayloadSize::B1024),
+            4 => Some(PciePayloadSize::B2048),
+            5 => Some(PciePayloadSize::B4096),
+            _ => None,
+        }
+    }
+
+    /// Returns the encoding of the size field.
+    pub fn to_bits(self) -> u8 {
+        match self {
+            PciePayloadSize::B128 => 0,
+            PciePayloadSize::B256 => 1,
+            PciePayloadSize::B512 => 2,
+            PciePayloadSize::B1024 => 3,
+            PciePayloadSize::B2048 => 4,
+            PciePayloadSize::B4096 => 5,
+        }
+    }
+
+    /// Returns the size in bytes.
+    pub fn bytes(self) -> usize {
+        128 << self.to_bits()
+    }
+}
+
+/// Speed of a PCI Express link.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum PcieLinkSpeed {
+    /// 2.5 GT/s.
+    Gt2_5,
+    /// 5 GT/s.
+    Gt5,
+    /// 8 GT/s.
+    Gt8,
+    /// 16 GT/s.
+    Gt16,
+    /// 32 GT/s.
+    Gt32,
+}
+
+impl PcieLinkSpeed {
+    /// Decodes a link speed field, returning `None` for reserved values.
+    pub fn from_bits(bits: u8) -> Option<Self> {
+        match bits {
+            1 => Some(PcieLinkSpeed::Gt2_5),
+            2 => Some(PcieLinkSpeed::Gt5),
+            3 => Some(PcieLinkSpeed::Gt8),
+            4 => Some(PcieLinkSpeed::Gt16),
+            5 => Some(PcieLinkSpeed::Gt32),
+            _ => None,
+        }
+    }
+
+    /// Returns the encoding of the link speed field.
+    pub fn to_bits(self) -> u8 {
+        match self {
+            PcieLinkSpeed::Gt2_5 => 1,
+            PcieLinkSpeed::Gt5 => 2,
+            PcieLinkSpeed::Gt8 => 3,
+            PcieLinkSpeed::Gt16 => 4,
+            PcieLinkSpeed::Gt32 => 5,
+        }
+    }
+}
+
+/// Width of a PCI Express link, in lanes.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum PcieLinkWidth {
+    /// x1.
+    X1,
+    /// x2.
+    X2,
+    /// x4.
+    X4,
+    /// x8.
+    X8,
+    /// x12.
+    X12,
+    /// x16.
+    X16,
+    /// x32.
+    X32,
+}
+
+impl PcieLinkWidth {
+    /// Decodes a link width field, returning `None` for reserved values.
+    pub fn from_bits(bits: u8) -> Option<Self> {
+        match bits {
+            1 => Some(PcieLinkWidth::X1),
+            2 => Some(PcieLinkWidth::X2),
+            4 => Some(PcieLinkWidth::X4),
+            8 => Some(PcieLinkWidth::X8),
+            12 => Some(PcieLinkWidth::X12),
+            16 => Some(PcieLinkWidth::X16),
+            32 => Some(PcieLinkWidth::X32),
+            _ => None,
+        }
+    }
+
+    /// Returns the encoding of the link width field, the number of lanes.
+    pub fn to_bits(self) -> u8 {
+        match self {
+            PcieLinkWidth::X1 => 1,
+            PcieLinkWidth::X2 => 2,
+            PcieLinkWidth::X4 => 4,
+            PcieLinkWidth::X8 => 8,
+            PcieLinkWidth::X12 => 12,
+            PcieLinkWidth::X16 => 16,
+            PcieLinkWidth::X32 => 32,
+        }
+    }
+}
+
+// Returns the link speed and width fields encoded for `speed` and `width`.
+fn encode_link(speed: PcieLinkSpeed, width: PcieLinkWidth) -> u32 {
+    u32::from(speed.to_bits()) | (u32::from(width.to_bits()) << LINK_WIDTH_SHIFT)
+}
+
+fn decode_speed(reg: u32) -> Option<PcieLinkSpeed> {
+    PcieLinkSpeed::from_bits((reg & LINK_SPEED_MASK) as u8)
+}
+
+fn decode_width(reg: u32) -> Option<PcieLinkWidth> {
+    PcieLinkWidth::from_bits(((reg >> LINK_WIDTH_SHIFT) & LINK_WIDTH_MASK) as u8)
+}
+
+/// PCI Express capability, version 2 layout.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct PciExpressCap {
+    /// PCI Express Capabilities register.
+    pub pcie_caps: u16,
+    /// Device Capabilities register.
+    pub dev_caps: u32,
+    /// Device Control register.
+    pub dev_ctl: u16,
+    /// Device Status register.
+    pub dev_status: u16,
+    /// Link Capabilities register.
+    pub link_caps: u32,
+    /// Link Control register.
+    pub link_ctl: u16,
+    /// Link Status register.
+    pub link_status: u16,
+    /// Slot Capabilities register.
+    pub slot_caps: u32,
+    /// Slot Control register.
+    pub slot_ctl: u16,
+    /// Slot Status register.
+    pub slot_status: u16,
+    /// Root Control register.
+    pub root_ctl: u16,
+    /// Root Capabilities register.
+    pub root_caps: u16,
+    /// Root Status register.
+    pub root_status: u32,
+    /// Device Capabilities 2 register.
+    pub dev_caps2: u32,
+    /// Device Control 2 register.
+    pub dev_ctl2: u16,
+    /// Device Status 2 register.
+    pub dev_status2: u16,
+    /// Link Capabilities 2 register.
+    pub link_caps2: u32,
+    /// Link Control 2 register.
+    pub link_ctl2: u16,
+    /// Link Status 2 register.
+    pub link_status2: u16,
+    /// Slot Capabilities 2 register.
+    pub slot_caps2: u32,
+    /// Slot Control 2 register.
+    pub slot_ctl2: u16,
+    /// Slot Status 2 register.
+    pub slot_status2: u16,
+}
+
+impl PciExpressCap {
+    /// ID of the capability.
+    pub const ID: PciCapabilityId = PciCapabilityId::PciExpress;
+    /// Length of the capability in bytes.
+    pub const LEN: usize = 0x3C;
+
+    /// Returns the Device/Port Type field, telling whether the function is an
+    /// endpoint, a root port, a switch port or a bridge.
+    pub fn device_port_type(&self) -> u8 {
+        ((self.pcie_caps >> CAPABILITIES_PORT_TYPE_SHIFT) & CAPABILITIES_PORT_TYPE_MASK) as u8
+    }
+
+    /// Returns the Max Link Speed of the Link Capabilities register, or
+    /// `None` if it holds a reserved value.
+    pub fn max_link_speed(&self) -> Option<PcieLinkSpeed> {
+        decode_speed(self.link_caps)
+    }
+
+    /// Returns the Maximum Link Width of the Link Capabilities register, or
+    /// `None` if it holds a reserved value.
+    pub fn max_link_width(&self) -> Option<PcieLinkWidth> {
+        decode_width(self.link_caps)
+    }
+
+    /// Sets the Max Link Speed and Maximum Link Width of the Link
+    /// Capabilities register.
+    pub fn set_max_link(&mut self, speed: PcieLinkSpeed, width: PcieLinkWidth) {
+        let mask = LINK_SPEED_MASK | (LINK_WIDTH_MASK << LINK_WIDTH_SHIFT);
+        self.link_caps = (self.link_caps & !mask) | encode_link(speed, width);
+    }
+
+    /// Returns the Current Link Speed of the Link Status register, or `None`
+    /// if it holds a reserved value.
+    pub fn link_speed(&self) -> Option<PcieLinkSpeed> {
+        decode_speed(u32::from(self.link_status))
+    }
+
+    /// Returns the Negotiated Link Width of the Link Status register, or
+    /// `None` if it holds a reserved value.
+    pub fn link_width(&self) -> Option<PcieLinkWidth> {
+        decode_width(u32::from(self.link_status))
+    }
+
+    /// Returns whether the Link Training bit of the Link Status register is
+    /// set.
+    pub fn link_training(&self) -> bool {
+        self.link_status & LINK_STATUS_TRAINING != 0
+    }
+
+    /// Sets the Current Link Speed and Negotiated Link Width of the Link
+    /// Status register, and clears the Link Training bit.
+    ///
+    /// Emulated links are usually reported as trained at a fixed speed and
+    /// width.
+    pub fn set_link_status(&mut self, speed: PcieLinkSpeed, width: PcieLinkWidth) {
+        let mask = LINK_SPEED_MASK | (LINK_WIDTH_MASK << LINK_WIDTH_SHIFT);
+        let status = (u32::from(self.link_status) & !mask) | encode_link(speed, width);
+        self.link_status = status as u16 & !LINK_STATUS_TRAINING;
+    }
+
+    /// Returns whether the reporting of `errors` is enabled in the Device
+    /// Control register.
+    pub fn error_reporting(&self, errors: PcieErrorReporting) -> bool {
+        self.dev_ctl & errors.bit() != 0
+    }
+
+    /// Enables or disables the reporting of `errors` in the Device Control
+    /// register.
+    pub fn set_error_reporting(&mut self, errors: PcieErrorReporting, enabled: bool) {
+        if enabled {
+            self.dev_ctl |= errors.bit();
+        } else {
+            self.dev_ctl &= !errors.bit();
+        }
+    }
+
+    /// Returns the Max Payload Size Supported of the Device Capabilities
+    /// register, or `None` if it holds a reserved value.
+    pub fn max_payload_size_supported(&self) -> Option<PciePayloadSize> {
+        PciePayloadSize::from_bits((self.dev_caps & DEVICE_CAPABILITIES_MPS_MASK) as u8)
+    }
+
+    /// Sets the Max Payload Size Supported of the Device Capabilities
+    /// register.
+    pub fn set_max_payload_size_supported(&mut self, size: PciePayloadSize) {
+        self.dev_caps = (self.dev_caps & !DEVICE_CAPABILITIES_MPS_MASK) | u32::from(size.to_bits());
+    }
+
+    /// Returns the Max Payload Size of the Device Control register, or
+    /// `None` if it holds a reserved value.
+    pub fn max_payload_size(&self) -> Option<PciePayloadSize> {
+        self.dev_ctl_size(DEVICE_CONTROL_MPS_SHIFT)
+    }
+
+    /// Sets the Max Payload Size of the Device Control register.
+    ///
+    /// Fails with `PayloadSizeUnsupported` if `size` is larger than the Max
+    /// Payload Size Supported, which must then be set first.
+    pub fn set_max_payload_size(&mut self, size: PciePayloadSize) -> Result<()> {
+        if self
+            .max_payload_size_supported()
+            .map_or(true, |supported| size > supported)
+        {
+            return Err(Error::PayloadSizeUnsupported(size));
+        }
+        self.set_dev_ctl_size(DEVICE_CONTROL_MPS_SHIFT, size);
+        Ok(())
+    }
+
+    /// Returns the Max Read Request Size of the Device Control register, or
+    /// `None` if it holds a reserved value.
+    pub fn max_read_request_size(&self) -> Option<PciePayloadSize> {
+        self.dev_ctl_size(DEVICE_CONTROL_MRRS_SHIFT)
+    }
+
+    /// Sets the Max Read Request Size of the Device Control register.
+    ///
+    /// Unlike the Max Payload Size, it isn't bounded by the capabilities of
+    /// the function.
+    pub fn set_max_read_request_size(&mut self, size: PciePayloadSize) {
+        self.set_dev_ctl_size(DEVICE_CONTROL_MRRS_SHIFT, size);
+    }
+
+    // Returns the size field of the Device Control register at `shift`.
+    fn dev_ctl_size(&self, shift: u32) -> Option<PciePayloadSize> {
+        PciePayloadSize::from_bits(((self.dev_ctl >> shift) & DEVICE_CONTROL_SIZE_MASK) as u8)
+    }
+
+    // Sets the size field of the Device Control register at `shift`.
+    fn set_dev_ctl_size(&mut self, shift: u32, size: PciePayloadSize) {
+        self.dev_ctl = (self.dev_ctl & !(DEVICE_CONTROL_SIZE_MASK << shift))
+            | (u16::from(size.to_bits()) << shift);
+    }
+
+    /// Reads the capability at `offset` of `cfg`.
+    ///
+    /// Fails with `CapabilityIdMismatch` if the capability there has another
+    /// ID, or `CapabilityListCorrupt` if it doesn't fit in the standard
+    /// configuration space.
+    pub fn from_config<C: PciConfig + ?Sized>(cfg: &C, offset: usize) -> pci_config::Result<Self> {
+        let bytes = capability::read_capability(cfg, offset, Self::ID, Self::LEN)?;
+        Ok(Self::from_bytes(&bytes))
+    }
+
+    pub(crate) fn from_bytes(bytes: &[u8]) -> Self {
+        PciExpressCap {
+            pcie_caps: read_u16(bytes, CAPABILITIES_OFFSET),
+            dev_caps: read_u32(bytes, DEVICE_CAPABILITIES_OFFSET),
+            dev_ctl: read_u16(bytes, DEVICE_CONTROL_OFFSET),
+            dev_status: read_u16(bytes, DEVICE_STATUS_OFFSET),
+            link_caps: read_u32(bytes, LINK_CAPABILITIES_OFFSET),
+            link_ctl: read_u16(bytes, LINK_CONTROL_OFFSET),
+            link_status: read_u16(bytes, LINK_STATUS_OFFSET),
+            slot_caps: read_u32(bytes, SLOT_CAPABILITIES_OFFSET),
+            slot_ctl: read_u16(bytes, SLOT_CONTROL_OFFSET),
+            slot_status: read_u16(bytes, SLOT_STATUS_OFFSET),
+            root_ctl: read_u16(bytes, ROOT_CONTROL_OFFSET),
+            root_caps: read_u16(bytes, ROOT_CAPABILITIES_OFFSET),
+            root_status: read_u32(bytes, ROOT_STATUS_OFFSET),
+            dev_caps2: read_u32(bytes, DEVICE_CAPABILITIES_2_OFFSET),
+            dev_ctl2: read_u16(bytes, DEVICE_CONTROL_2_OFFSET),
+            dev_status2: read_u16(bytes, DEVICE_STATUS_2_OFFSET),
+            link_caps2: read_u32(bytes, LINK_CAPABILITIES_2_OFFSET),
+            link_ctl2: read_u16(bytes, LINK_CONTROL_2_OFFSET),
+            link_status2: read_u16(bytes, LINK_STATUS_2_OFFSET),
+            slot_caps2: read_u32(bytes, SLOT_CAPABILITIES_2_OFFSET),
+            slot_ctl2: read_u16(bytes, SLOT_CONTROL_2_OFFSET),
+            slot_status2: read_u16(bytes, SLOT_STATUS_2_OFFSET),
+        }
+    }
+}
+
+impl PciCapability for PciExpressCap {
+    fn id(&self) -> PciCapabilityId {
+        Self::ID
+    }
+
+    fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = vec![0; Self::LEN];
+        write_u16(&mut bytes, CAPABILITIES_OFFSET, self.pcie_caps);
+        write_u32(&mut bytes, DEVICE_CAPABILITIES_OFFSET, self.dev_caps);
+        write_u16(&mut bytes, DEVICE_CONTROL_OFFSET, self.dev_ctl);
+        write_u16(&mut bytes, DEVICE_STATUS_OFFSET, self.dev_status);
+        write_u32(&mut bytes, LINK_CAPABILITIES_OFFSET, self.link_caps);
+        write_u16(&mut bytes, LINK_CONTROL_OFFSET, self.link_ctl);
+        write_u16(&mut bytes, LINK_STATUS_OFFSET, self.link_status);
+        write_u32(&mut bytes, SLOT_CAPABILITIES_OFFSET, self.slot_caps);
+        write_u16(&mut bytes, SLOT_CONTROL_OFFSET, self.slot_ctl);
+        write_u16(&mut bytes, SLOT_STATUS_OFFSET, self.slot_status);
+        write_u16(&mut bytes, ROOT_CONTROL_OFFSET, self.root_ctl);
+        write_u16(&mut bytes, ROOT_CAPABILITIES_OFFSET, self.root_caps);
+        write_u32(&mut bytes, ROOT_STATUS_OFFSET, self.root_status);
+        write_u32(&mut bytes, DEVICE_CAPABILITIES_2_OFFSET, self.dev_caps2);
+        write_u16(&mut bytes, DEVICE_CONTROL_2_OFFSET, self.dev_ctl2);
+        write_u16(&mut bytes, DEVICE_STATUS_2_OFFSET, self.dev_status2);
+        write_u32(&mut bytes, LINK_CAPABILITIES_2_OFFSET, self.link_caps2);
+        write_u16(&mut bytes, LINK_CONTROL_2_OFFSET, self.link_ctl2);
+        write_u16(&mut bytes, LINK_STATUS_2_OFFSET, self.link_status2);
+        write_u32(&mut bytes, SLOT_CAPABILITIES_2_OFFSET, self.slot_caps2);
+        write_u16(&mut bytes, SLOT_CONTROL_2_OFFSET, self.slot_ctl2);
+        write_u16(&mut bytes, SLOT_STATUS_2_OFFSET, self.slot_status2);
+        bytes
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_link_caps_encoding() {
+        let mut cap = PciExpressCap {
+            link_caps: 0xFFFF_FC00,
+            ..Default::default()
+        };
+        // Max Link Speed in bits 3:0, Maximum Link Width in bits 9:4.
+        cap.set_max_link(PcieLinkSpeed::Gt8, PcieLinkWidth::X16);
+        assert_eq!(cap.link_caps, 0xFFFF_FD03);
+        assert_eq!(cap.max_link_speed(), Some(PcieLinkSpeed::Gt8));
+        assert_eq!(cap.max_link_width(), Some(PcieLinkWidth::X16));
+
+        cap.link_caps = 0x0000_0206;
+        assert_eq!(cap.max_link_speed(), None);
+        assert_eq!(cap.max_link_width(), Some(PcieLinkWidth::X32));
+        cap.link_caps = 0x0000_0031;
+        assert_eq!(cap.max_link_speed(), Some(PcieLinkSpeed::Gt2_5));
+        assert_eq!(cap.max_link_width(), None);
+    }
+
+    #[test]
+    fn test_link_status_encoding() {
+        let mut cap = PciExpressCap {
+            link_status: LINK_STATUS_TRAINING | 0x2000,
+            ..Default::default()
+        };
+        assert!(cap.link_training());
+        // Current Link Speed in bits 3:0, Negotiated Link Width in bits 9:4.
+        cap.set_link_status(PcieLinkSpeed::Gt32, PcieLinkWidth::X4);
+        assert_eq!(cap.link_status, 0x2045);
+        assert!(!cap.link_training());
+
+        let cap = PciExpressCap::from_bytes(&cap.to_bytes());
+        assert_eq!(cap.link_speed(), Some(PcieLinkSpeed::Gt32));
+        assert_eq!(cap.link_width(), Some(PcieLinkWidth::X4));
+    }
+
+    #[test]
+    fn test_link_round_trip() {
+        let speeds = [
+            PcieLinkSpeed::Gt2_5,
+            PcieLinkSpeed::Gt5,
+            PcieLinkSpeed::Gt8,
+            PcieLinkSpeed::Gt16,
+            PcieLinkSpeed::Gt32,
+        ];
+        let widths = [
+            PcieLinkWidth::X1,
+            PcieLinkWidth::X2,
+            PcieLinkWidth::X4,
+            PcieLinkWidth::X8,
+            PcieLinkWidth::X12,
+            PcieLinkWidth::X16,
+            PcieLinkWidth::X32,
+        ];
+        for speed in speeds.iter() {
+            assert_eq!(PcieLinkSpeed::from_bits(speed.to_bits()), Some(*speed));
+        }
+        for width in widths.iter() {
+            assert_eq!(PcieLinkWidth::from_bits(width.to_bits()), Some(*width));
+        }
+        assert_eq!(PcieLinkSpeed::from_bits(0), None);
+        assert_eq!(PcieLinkWidth::from_bits(3), None);
+    }
+
+    #[test]
+    fn test_payload_size_encoding() {
+        let mut cap = PciExpressCap {
+            dev_caps: 0xFFFF_FFF8,
+            dev_ctl: 0x800F,
+            ..Default::default()
+        };
+        // Max Payload Size Supported in bits 2:0 of Device Capabilities.
+        cap.set_max_payload_size_supported(PciePayloadSize::B512);
+        assert_eq!(cap.dev_caps, 0xFFFF_FFFA);
+        assert_eq!(
+            cap.max_payload_size_supported(),
+            Some(PciePayloadSize::B512)
+        );
+
+        // Max Payload Size in bits 7:5, Max Read Request Size in bits 14:12
+        // of Device Control.
+        cap.set_max_payload_size(PciePayloadSize::B256).unwrap();
+        cap.set_max_read_request_size(PciePayloadSize::B4096);
+        assert_eq!(cap.dev_ctl, 0xD02F);
+        assert_eq!(
+            cap.set_max_payload_size(PciePayloadSize::B1024),
+            Err(Error::PayloadSizeUnsupported(PciePayloadSize::B1024))
+        );
+
+        let cap = PciExpressCap::from_bytes(&cap.to_bytes());
+        assert_eq!(cap.max_payload_size(), Some(PciePayloadSize::B256));
+        assert_eq!(cap.max_read_request_size(), Some(PciePayloadSize::B4096));
+        assert!(cap.error_reporting(PcieErrorReporting::UnsupportedRequest));
+    }
+
+    #[test]
+    fn test_payload_size_reserved() {
+        let mut cap = PciExpressCap {
+            dev_caps: 0x0000_0006,
+            dev_ctl: 0x70E0,
+            ..Default::default()
+        };
+        assert_eq!(cap.max_payload_size_supported(), None);
+        assert_eq!(cap.max_payload_size(), None);
+        assert_eq!(cap.max_read_request_size(), None);
+        assert_eq!(
+            cap.set_max_payload_size(PciePayloadSize::B128),
+            Err(Error::PayloadSizeUnsupported(PciePayloadSize::B128))
+        );
+        for bits in 0..6 {
+            let size = PciePayloadSize::from_bits(bits).unwrap();
+            assert_eq!(size.to_bits(), bits);
+            assert_eq!(size.bytes(), 128 << bits);
+        }
+    }
+}
@@ -0,0 +1,220 @@
+//! PCI Power Management capability.
+
+use std::fmt;
+
+use crate::capability::{self, read_u16, write_u16, PciCapability, PciCapabilityId};
+use crate::pci_config::{self, PciConfig};
+
+/// Offset of the Power Management Capabilities register.
+pub const PMC_OFFSET: usize = 0x02;
+/// Offset of the Power Management Control/Status register.
+pub const PMCSR_OFFSET: usize = 0x04;
+/// Offset of the PMCSR bridge support extensions.
+pub const PMCSR_BSE_OFFSET: usize = 0x06;
+/// Offset of the Data register.
+pub const DATA_OFFSET: usize = 0x07;
+
+/// Power Management Capabilities bits holding the version of the
+/// specification the function complies with.
+pub const PMC_VERSION_MASK: u16 = 0x7;
+/// Version field of revision 1.2 of the Power Management specification.
+pub const PMC_VERSION_1_2: u16 = 0x3;
+/// Power Management Capabilities bit set when D1 is supported.
+pub const PMC_D1_SUPPORT: u16 = 1 << 9;
+/// Power Management Capabilities bit set when D2 is supported.
+pub const PMC_D2_SUPPORT: u16 = 1 << 10;
+/// Power Management Control/Status bits holding the current power state.
+pub const PMCSR_POWER_STATE_MASK: u16 = 0x3;
+/// Shift of the PME_Support field in the Power Management Capabilities
+/// register. The field holds one bit per power state, D0 first.
+pub const PMC_PME_SUPPORT_SHIFT: u32 = 11;
+
+/// Device power state.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PowerState {
+    /// Fully on.
+    D0,
+    /// Light sleep.
+    D1,
+    /// Deeper sleep.
+    D2,
+    /// Off, with power applied.
+    D3Hot,
+    /// Off, without power.
+    D3Cold,
+}
+
+impl PowerState {
+    // Returns the bit of the state in the PME_Support field.
+    fn pme_support_bit(self) -> u16 {
+        let shift = match self {
+            PowerState::D0 => 0,
+            PowerState::D1 => 1,
+            PowerState::D2 => 2,
+            PowerState::D3Hot => 3,
+            PowerState::D3Cold => 4,
+        };
+        1 << (PMC_PME_SUPPORT_SHIFT + shift)
+    }
+}
+
+/// Errors related to the Power Management capability.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Error {
+    /// PME generation is claimed from a power state that isn't supported.
+    PmeFromUnsupportedState(PowerState),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::PmeFromUnsupportedState(state) => {
+                write!(f, "PME generation from unsupported state {:?}", state)
+            }
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+/// Result of the Power Management capability operations.
+pub type Result<T> = std::result::Result<T, Error>;
+
+/// Power Management capability.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct PowerManagementCap {
+    /// Power Management Capabilities register.
+    pub pmc: u16,
+    /// Power Management Control/Status register.
+    pub pmcsr: u16,
+    /// PMCSR bridge support extensions.
+    pub pmcsr_bse: u8,
+    /// Data register.
+    pub data: u8,
+}
+
+impl PowerManagementCap {
+    /// ID of the capability.
+    pub const ID: PciCapabilityId = PciCapabilityId::PowerManagement;
+    /// Length of the capability in bytes.
+    pub const LEN: usize = 8;
+
+    /// Creates a revision 1.2 capability for a function in D0.
+    ///
+    /// D0 and the D3 states are always supported, `d1` and `d2` tell whether
+    /// the function also supports D1 and D2. `pme_states` lists the states the
+    /// function can generate PME from; they must all be supported.
+    pub fn new(d1: bool, d2: bool, pme_states: &[PowerState]) -> Result<Self> {
+        let mut pmc = PMC_VERSION_1_2;
+        if d1 {
+            pmc |= PMC_D1_SUPPORT;
+        }
+        if d2 {
+            pmc |= PMC_D2_SUPPORT;
+        }
+        let mut cap = PowerManagementCap {
+            pmc,
+            ..Default::default()
+        };
+        for &state in pme_states {
+            if !cap.supports(state) {
+                return Err(Error::PmeFromUnsupportedState(state));
+            }
+            cap.pmc |= state.pme_support_bit();
+        }
+        Ok(cap)
+    }
+
+    /// Returns whether the function supports the power state `state`.
+    ///
+    /// D0, D3hot and D3cold are always supported.
+    pub fn supports(&self, state: PowerState) -> bool {
+        match state {
+            PowerState::D1 => self.pmc & PMC_D1_SUPPORT != 0,
+            PowerState::D2 => self.pmc & PMC_D2_SUPPORT != 0,
+            PowerState::D0 | PowerState::D3Hot | PowerState::D3Cold => true,
+        }
+    }
+
+    /// Returns whether the function can generate PME from the power state
+    /// `state`.
+    pub fn pme_supported(&self, state: PowerState) -> bool {
+        self.pmc & state.pme_support_bit() != 0
+    }
+
+    /// Returns the current power state of the function.
+    ///
+    /// D3cold can't be read from the registers, as the function is
+    /// unpowered in that state.
+    pub fn power_state(&self) -> PowerState {
+        match self.pmcsr & PMCSR_POWER_STATE_MASK {
+            0 => PowerState::D0,
+            1 => PowerState::D1,
+            2 => PowerState::D2,
+            _ => PowerState::D3Hot,
+        }
+    }
+
+    /// Reads the capability at `offset` of `cfg`.
+    ///
+    /// Fails with `CapabilityIdMismatch` if the capability there has another
+    /// ID, or `CapabilityListCorrupt` if it doesn't fit in the standard
+    /// configuration space.
+    pub fn from_config<C: PciConfig + ?Sized>(cfg: &C, offset: usize) -> pci_config::Result<Self> {
+        let bytes = capability::read_capability(cfg, offset, Self::ID, Self::LEN)?;
+        Ok(Self::from_bytes(&bytes))
+    }
+
+    pub(crate) fn from_bytes(bytes: &[u8]) -> Self {
+        PowerManagementCap {
+            pmc: read_u16(bytes, PMC_OFFSET),
+            pmcsr: read_u16(bytes, PMCSR_OFFSET),
+            pmcsr_bse: bytes[PMCSR_BSE_OFFSET],
+            data: bytes[DATA_OFFSET],
+        }
+    }
+}
+
+impl PciCapability for PowerManagementCap {
+    fn id(&self) -> PciCapabilityId {
+        Self::ID
+    }
+
+    fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = vec![0; Self::LEN];
+        write_u16(&mut bytes, PMC_OFFSET, self.pmc);
+        write_u16(&mut bytes, PMCSR_OFFSET, self.pmcsr);
+        bytes[PMCSR_BSE_OFFSET] = self.pmcsr_bse;
+        bytes[DATA_OFFSET] = self.data;
+        bytes
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_supported_states() {
+        let cap =
+            PowerManagementCap::new(true, false, &[PowerState::D0, PowerState::D3Hot]).unwrap();
+        let bytes = cap.to_bytes();
+        // Version 1.2, D1 support, PME from D0 and D3hot.
+        assert_eq!(read_u16(&bytes, PMC_OFFSET), 0x4A03);
+        assert!(cap.supports(PowerState::D1));
+        assert!(!cap.supports(PowerState::D2));
+        assert!(cap.supports(PowerState::D3Hot));
+        assert!(cap.pme_supported(PowerState::D3Hot));
+        assert!(!cap.pme_supported(PowerState::D1));
+
+        let cap =
+            PowerManagementCap::new(false, true, &[PowerState::D2, PowerState::D3Cold]).unwrap();
+        // Version 1.2, D2 support, PME from D2 and D3cold.
+        assert_eq!(read_u16(&cap.to_bytes(), PMC_OFFSET), 0xA403);
+
+        assert_eq!(
+            PowerManagementCap::new(false, false, &[PowerState::D1]),
+            Err(Error::PmeFromUnsupportedState(PowerState::D1))
+        );
+    }
+}
@@ -0,0 +1,207 @@
+//! Resets of the configuration space.
+//!
+//! Resets return the function to its initial state, except for the sticky
+//! (RWS) bits, such as the sticky error status bits of Advanced Error
+//! Reporting, which survive every reset but a cold one. A
+//! [`ResetPolicy`](struct.ResetPolicy.html) classifies the bits of each
+//! register:
+//!
+//! - volatile bits are cleared by the reset;
+//! - sticky bits are preserved, and cleared by a cold reset;
+//! - hardware-initialized bits are reset to a default value.
+
+use std::collections::BTreeMap;
+
+use crate::pci_config::{PciConfig, Result};
+
+/// Kind of reset applied to a function.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ResetKind {
+    /// Function Level Reset, of a single function.
+    FunctionLevel,
+    /// Hot reset, signaled by the upstream port over the link.
+    Hot,
+    /// Warm reset, the main power staying on.
+    Warm,
+    /// Cold reset, following the application of main power.
+    Cold,
+}
+
+// Reset behavior of the bits of a register. Bits that are neither sticky nor
+// hardware-initialized are volatile.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+struct RegisterReset {
+    sticky: u32,
+    init_mask: u32,
+    init_value: u32,
+}
+
+/// Reset behavior of the bits of the configuration space.
+///
+/// Every bit is volatile unless the policy says otherwise.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct ResetPolicy {
+    registers: BTreeMap<usize, RegisterReset>,
+}
+
+impl ResetPolicy {
+    /// Creates a policy where every bit is volatile.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Creates a policy where the current value of every register of `cfg` is
+    /// its hardware-initialized value.
+    ///
+    /// This is meant to be called once the device is set up, before the guest
+    /// runs, then refined with [`volatile`](#method.volatile) and
+    /// [`sticky`](#method.sticky).
+    pub fn from_initial_state<C: PciConfig + ?Sized>(cfg: &C) -> Result<Self> {
+        let mut registers = BTreeMap::new();
+        for reg_idx in 0..cfg.size() / 4 {
+            let value = cfg.read_register(reg_idx)?;
+            registers.insert(
+                reg_idx,
+                RegisterReset {
+                    sticky: 0,
+                    init_mask: u32::MAX,
+                    init_value: value,
+                },
+            );
+        }
+        Ok(ResetPolicy { registers })
+    }
+
+    /// Marks the `mask` bits of the register at `reg_idx` as volatile.
+    pub fn volatile(mut self, reg_idx: usize, mask: u32) -> Self {
+        let reg = self.registers.entry(reg_idx).or_default();
+        reg.sticky &= !mask;
+        reg.init_mask &= !mask;
+        self
+    }
+
+    /// Marks the `mask` bits of the register at `reg_idx` as sticky.
+    pub fn sticky(mut self, reg_idx: usize, mask: u32) -> Self {
+        let reg = self.registers.entry(reg_idx).or_default();
+        reg.sticky |= mask;
+        reg.init_mask &= !mask;
+        self
+    }
+
+    /// Marks the `mask` bits of the register at `reg_idx` as
+    /// hardware-initialized to the matching bits of `value`.
+    pub fn hardware_init(mut self, reg_idx: usize, mask: u32, value: u32) -> Self {
+        let reg = self.registers.entry(reg_idx).or_default();
+        reg.sticky &= !mask;
+        reg.init_mask |= mask;
+        reg.init_value = (reg.init_value & !mask) | (value & mask);
+        self
+    }
+
+    // Returns the value of a register holding `value` after a reset of kind
+    // `kind`.
+    pub(crate) fn reset_value(&self, reg_idx: usize, value: u32, kind: ResetKind) -> u32 {
+        let reg = self.registers.get(&reg_idx).copied().unwrap_or_default();
+        let sticky = match kind {
+            ResetKind::Cold => 0,
+            _ => value & reg.sticky,
+        };
+        sticky | (reg.init_value & reg.init_mask)
+    }
+}
+
+pub(crate) fn reset<C: PciConfig + ?Sized>(
+    cfg: &mut C,
+    kind: ResetKind,
+    policy: &ResetPolicy,
+) -> Result<()> {
+    for reg_idx in 0..cfg.size() / 4 {
+        let value = cfg.read_register(reg_idx)?;
+        cfg.write_register(reg_idx, policy.reset_value(reg_idx, value, kind))?;
+    }
+    Ok(())
+}
+
+// Resets the stored `registers` in place, regardless of the bits the guest
+// can write.
+pub(crate) fn reset_registers(registers: &mut [u32], kind: ResetKind, policy: &ResetPolicy) {
+    for (reg_idx, value) in registers.iter_mut().enumerate() {
+        *value = policy.reset_value(reg_idx, *value, kind);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config_space::ConfigSpace;
+    use crate::pci_config::{STATUS_INTERRUPT, STATUS_OFFSET};
+
+    // Capability at 0x40: a read-only register holding a device-managed
+    // value, a guest-writable one and a read-only status register.
+    const CAP_REG: usize = 0x40 / 4;
+    const WRITABLE_REG: usize = 0x44 / 4;
+    const STATUS_REG: usize = 0x48 / 4;
+
+    fn setup() -> (ConfigSpace, ResetPolicy) {
+        let mut cfg = ConfigSpace::minimal(0x1234, 0x5678, 0xff, 0x00);
+        cfg.set_capability_writable(0x40, 12, &[(4, 4)]).unwrap();
+        cfg.as_registers_mut()[CAP_REG] = 0x0010_0009;
+        let policy = ResetPolicy::from_initial_state(&cfg)
+            .unwrap()
+            .sticky(WRITABLE_REG, 0x0000_00ff)
+            .volatile(WRITABLE_REG, 0xffff_ff00)
+            .volatile(STATUS_REG, u32::MAX);
+        (cfg, policy)
+    }
+
+    #[test]
+    fn test_flr_reset() {
+        let (mut cfg, policy) = setup();
+        cfg.as_registers_mut()[CAP_REG] = 0xdead_beef;
+        cfg.as_registers_mut()[STATUS_REG] = 0x0000_ffff;
+        cfg.write_register(WRITABLE_REG, 0x1234_5678).unwrap();
+        cfg.set_intx_asserted(true);
+
+        cfg.flr_reset(&policy).unwrap();
+        assert_eq!(cfg.read_register(CAP_REG).unwrap(), 0x0010_0009);
+        assert_eq!(cfg.read_register(WRITABLE_REG).unwrap(), 0x0000_0078);
+        assert_eq!(cfg.read_register(STATUS_REG).unwrap(), 0);
+        assert!(!cfg.intx_asserted());
+        assert_eq!(cfg.read_word(STATUS_OFFSET).unwrap() & STATUS_INTERRUPT, 0);
+        assert_eq!(cfg.read_word(0).unwrap(), 0x1234);
+    }
+
+    #[test]
+    fn test_sticky_bits() {
+        for kind in [ResetKind::FunctionLevel, ResetKind::Hot, ResetKind::Warm].iter() {
+            let (mut cfg, policy) = setup();
+            cfg.write_register(WRITABLE_REG, 0xffff_ffff).unwrap();
+            cfg.apply_reset(*kind, &policy).unwrap();
+            assert_eq!(cfg.read_register(WRITABLE_REG).unwrap(), 0x0000_00ff);
+        }
+
+        let (mut cfg, policy) = setup();
+        cfg.write_register(WRITABLE_REG, 0xffff_ffff).unwrap();
+        cfg.apply_reset(ResetKind::Cold, &policy).unwrap();
+        assert_eq!(cfg.read_register(WRITABLE_REG).unwrap(), 0);
+    }
+
+    #[test]
+    fn test_flr_reset_through_wrappers() {
+        use crate::hidden::HiddenCapabilities;
+        use crate::msix_control::MsiXControlConfig;
+        use crate::notify::NotifyConfig;
+        use crate::shadow::ShadowConfig;
+
+        let (mut cfg, policy) = setup();
+        cfg.set_intx_asserted(true);
+        let config = MsiXControlConfig::new(cfg, 0x40);
+        let mut config = HiddenCapabilities::new(NotifyConfig::new(ShadowConfig::new(config)));
+        config.write_register(WRITABLE_REG, 0x1234_5678).unwrap();
+
+        config.flr_reset(&policy).unwrap();
+        let cfg = config.inner().inner().inner().inner();
+        assert!(!cfg.intx_asserted());
+        assert_eq!(config.read_register(WRITABLE_REG).unwrap(), 0x0000_0078);
+    }
+}
@@ -0,0 +1,99 @@
+//! Split configuration space for device passthrough.
+//!
+//! A passthrough device exposes most of its configuration space directly,
+//! but registers such as the BARs, the command register or the MSI-X control
+//! word have to be virtualized by the VMM. [`ShadowConfig`] keeps an
+//! in-memory copy of the virtualized registers and forwards every other
+//! access to the backing configuration space.
+
+use std::collections::BTreeMap;
+
+use crate::pci_config::{AccessWidth, PciConfig, Result};
+use crate::reset::{ResetKind, ResetPolicy};
+
+/// Configuration space whose virtualized registers are served from a shadow
+/// copy instead of the backing configuration space.
+pub struct ShadowConfig<T: PciConfig> {
+    inner: T,
+    shadow: BTreeMap<usize, u32>,
+}
+
+impl<T: PciConfig> ShadowConfig<T> {
+    /// Wraps `inner` without virtualizing any register.
+    pub fn new(inner: T) -> Self {
+        ShadowConfig {
+            inner,
+            shadow: BTreeMap::new(),
+        }
+    }
+
+    /// Virtualizes the register at `reg_idx`.
+    ///
+    /// The shadow copy starts with the current value of the backing register.
+    /// Virtualizing an already virtualized register keeps its shadow value.
+    pub fn virtualize(&mut self, reg_idx: usize) -> Result<()> {
+        if !self.shadow.contains_key(&reg_idx) {
+            let value = self.inner.read_register(reg_idx)?;
+            self.shadow.insert(reg_idx, value);
+        }
+        Ok(())
+    }
+
+    /// Returns whether the register at `reg_idx` is virtualized.
+    pub fn is_virtualized(&self, reg_idx: usize) -> bool {
+        self.shadow.contains_key(&reg_idx)
+    }
+
+    /// Returns the backing configuration space.
+    pub fn inner(&self) -> &T {
+        &self.inner
+    }
+
+    /// Returns the backing configuration space mutably.
+    ///
+    /// Writes done through this reference bypass the shadow copy.
+    pub fn inner_mut(&mut self) -> &mut T {
+        &mut self.inner
+    }
+
+    /// Consumes the wrapper and returns the backing configuration space.
+    pub fn into_inner(self) -> T {
+        self.inner
+    }
+}
+
+impl<T: PciConfig> PciConfig for ShadowConfig<T> {
+    fn read_register(&self, reg_idx: usize) -> Result<u32> {
+        match self.shadow.get(&reg_idx) {
+            Some(value) => Ok(*value),
+            None => self.inner.read_register(reg_idx),
+        }
+    }
+
+    fn write_register(&mut self, reg_idx: usize, value: u32) -> Result<()> {
+        match self.shadow.get_mut(&reg_idx) {
+            Some(shadow) => {
+                *shadow = value;
+                Ok(())
+            }
+            None => self.inner.write_register(reg_idx, value),
+        }
+    }
+
+    fn size(&self) -> usize {
+        self.inner.size()
+    }
+
+    fn register_access_policy(&self, reg_idx: usize) -> AccessWidth {
+        self.inner.register_access_policy(reg_idx)
+    }
+
+    // The shadow copies are reset along with the backing registers.
+    fn apply_reset(&mut self, kind: ResetKind, policy: &ResetPolicy) -> Result<()> {
+        self.inner.apply_reset(kind, policy)?;
+        for (reg_idx, value) in self.shadow.iter_mut() {
+            *value = policy.reset_value(*reg_idx, *value, kind);
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,54 @@
+//! Configuration space shared between threads.
+
+use std::sync::{Mutex, MutexGuard};
+
+use crate::pci_config::{PciConfig, Result};
+
+/// Configuration space that can be accessed from several threads.
+///
+/// The word and byte writes of [`PciConfig`](../pci_config/trait.PciConfig.html)
+/// read the whole register, modify it and write it back. `SyncConfig` runs
+/// each access under a lock, so concurrent accesses to the same register,
+/// for instance from a vCPU thread and a management thread, can't lose
+/// updates.
+#[derive(Debug, Default)]
+pub struct SyncConfig<T: PciConfig> {
+    inner: Mutex<T>,
+}
+
+impl<T: PciConfig> SyncConfig<T> {
+    /// Wraps the configuration space `inner`.
+    pub fn new(inner: T) -> Self {
+        SyncConfig {
+            inner: Mutex::new(inner),
+        }
+    }
+
+    /// Locks the configuration space, for sequences of accesses that must
+    /// not be interleaved with accesses from other threads.
+    ///
+    /// A thread panicking while holding the lock doesn't prevent other
+    /// threads from locking the configuration space again.
+    pub fn lock(&self) -> MutexGuard<'_, T> {
+        self.inner.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Reads `data.len()` bytes at `offset`, as a bus access of that width.
+    ///
+    /// See [`PciConfig::read_data`](../pci_config/trait.PciConfig.html#method.read_data).
+    pub fn read_data(&self, offset: usize, data: &mut [u8]) -> Result<()> {
+        self.lock().read_data(offset, data)
+    }
+
+    /// Writes `data` at `offset`, as a bus access of `data.len()` bytes.
+    ///
+    /// See [`PciConfig::write_data`](../pci_config/trait.PciConfig.html#method.write_data).
+    pub fn write_data(&self, offset: usize, data: &[u8]) -> Result<()> {
+        self.lock().write_data(offset, data)
+    }
+
+    /// Unwraps the configuration space.
+    pub fn into_inner(self) -> T {
+        self.inner.into_inner().unwrap_or_else(|e| e.into_inner())
+    }
+}